@@ -11,11 +11,91 @@ pub struct Position {
 pub struct Radius {
     pub r: f32,
 }
+/// World units per second
 #[derive(Debug, Default, Component)]
 pub struct Veclocity {
     pub vel: Vec2,
 }
 
+/// Scales the contribution of a ball, negative weights carve holes
+#[derive(Debug, Component)]
+pub struct Weight {
+    pub w: f32,
+}
+
+impl Default for Weight {
+    fn default() -> Self {
+        Self { w: 1.0 }
+    }
+}
+
+/// Falloff of a ball over the distance to its center. Supports are given
+/// in multiples of the ball radius.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Component)]
+pub enum Kernel {
+    /// r² / d², singular at the center and never reaching zero
+    #[default]
+    InverseSquare,
+    /// Wyvill soft object polynomial, 1 at the center and 0 from the support
+    /// on with zero slope at both ends
+    Wyvill { support: f32 },
+    /// Blinn exponential, 1 at the center and falling faster with higher
+    /// blobbiness, never reaching zero
+    Blinn { blobbiness: f32 },
+    /// Nishimura piecewise quadratic, 1 at the center and 0 from the
+    /// support on
+    Nishimura { support: f32 },
+}
+
+impl Kernel {
+    pub fn value(&self, r: f32, distance_squared: f32) -> f32 {
+        match *self {
+            Kernel::InverseSquare => r.powi(2) / distance_squared,
+            Kernel::Wyvill { support } => {
+                let a2 = distance_squared / (support * r).powi(2);
+                if a2 >= 1.0 {
+                    return 0.0;
+                }
+                1.0 - (4.0 / 9.0) * a2.powi(3) + (17.0 / 9.0) * a2.powi(2) - (22.0 / 9.0) * a2
+            }
+            Kernel::Blinn { blobbiness } => (-blobbiness * distance_squared / r.powi(2)).exp(),
+            Kernel::Nishimura { support } => {
+                let a = distance_squared.sqrt() / (support * r);
+                if a < 1.0 / 3.0 {
+                    1.0 - 3.0 * a * a
+                } else if a < 1.0 {
+                    1.5 * (1.0 - a).powi(2)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// Distance from which the kernel is zero, `None` when it never is
+    pub fn support(&self, r: f32) -> Option<f32> {
+        match *self {
+            Kernel::InverseSquare | Kernel::Blinn { .. } => None,
+            Kernel::Wyvill { support } | Kernel::Nishimura { support } => Some(support * r),
+        }
+    }
+}
+
+/// Everything needed to evaluate one ball, detached from the ECS
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metaball {
+    pub pos: Vec2,
+    pub r: f32,
+    pub kernel: Kernel,
+    pub weight: f32,
+}
+
+impl Metaball {
+    pub fn value(&self, x: f32, y: f32) -> f32 {
+        Ball::calc(&self.pos, self.r, &self.kernel, self.weight, x, y)
+    }
+}
+
 pub fn setup(mut commands: Commands) {
     commands
         .spawn()
@@ -25,7 +105,7 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 5.0 })
         .insert(Veclocity {
-            vel: Vec2::new(0.1, -0.4),
+            vel: Vec2::new(6.0, -24.0),
         });
     commands
         .spawn()
@@ -35,7 +115,7 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 2.0 })
         .insert(Veclocity {
-            vel: Vec2::new(-0.3, 0.1),
+            vel: Vec2::new(-18.0, 6.0),
         });
     commands
         .spawn()
@@ -45,7 +125,7 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 3.0 })
         .insert(Veclocity {
-            vel: Vec2::new(-0.2, 0.3),
+            vel: Vec2::new(-12.0, 18.0),
         });
     commands
         .spawn()
@@ -55,7 +135,7 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 1.4 })
         .insert(Veclocity {
-            vel: Vec2::new(0.87, 1.111),
+            vel: Vec2::new(52.2, 66.66),
         });
     commands
         .spawn()
@@ -65,7 +145,7 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 2.1 })
         .insert(Veclocity {
-            vel: Vec2::new(0.5, -0.2),
+            vel: Vec2::new(30.0, -12.0),
         });
     commands
         .spawn()
@@ -75,12 +155,102 @@ pub fn setup(mut commands: Commands) {
         })
         .insert(Radius { r: 1.2 })
         .insert(Veclocity {
-            vel: Vec2::new(-1.1, 0.9),
+            vel: Vec2::new(-66.0, 54.0),
         });
+    commands
+        .spawn()
+        .insert(Ball)
+        .insert(Position {
+            pos: Vec2::new(-10.0, 10.0),
+        })
+        .insert(Radius { r: 3.0 })
+        .insert(Veclocity {
+            vel: Vec2::new(24.0, 18.0),
+        })
+        .insert(Kernel::Wyvill { support: 2.0 })
+        .insert(Weight { w: -1.0 });
 }
 
 impl Ball {
-    pub fn calc(pos: &Vec2, r: f32, x: f32, y: f32) -> f32 {
-        r.powi(2) / ((pos.x - x).powi(2) + (pos.y - y).powi(2))
+    pub fn calc(pos: &Vec2, r: f32, kernel: &Kernel, weight: f32, x: f32, y: f32) -> f32 {
+        weight * kernel.value(r, (pos.x - x).powi(2) + (pos.y - y).powi(2))
+    }
+
+    pub fn calc_3d(
+        pos: &Vec3,
+        r: f32,
+        kernel: &Kernel,
+        weight: f32,
+        x: f32,
+        y: f32,
+        z: f32,
+    ) -> f32 {
+        let distance_squared = (pos.x - x).powi(2) + (pos.y - y).powi(2) + (pos.z - z).powi(2);
+        weight * kernel.value(r, distance_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNELS: [Kernel; 4] = [
+        Kernel::InverseSquare,
+        Kernel::Wyvill { support: 2.0 },
+        Kernel::Blinn { blobbiness: 0.5 },
+        Kernel::Nishimura { support: 2.0 },
+    ];
+
+    #[test]
+    fn kernels_fall_off() {
+        let r = 3.0;
+        for kernel in KERNELS {
+            let values: Vec<f32> = (0..=24)
+                .map(|i| kernel.value(r, (0.25 * i as f32 + 0.1).powi(2)))
+                .collect();
+            assert!(
+                values.windows(2).all(|w| w[1] <= w[0]),
+                "{:?} is not falling: {:?}",
+                kernel,
+                values
+            );
+            assert!(values.iter().all(|v| *v >= 0.0));
+        }
+        assert_eq!(Kernel::InverseSquare.value(r, r * r), 1.0);
+    }
+
+    #[test]
+    fn bounded_kernels_start_at_one_and_reach_zero_at_the_support() {
+        let r = 3.0;
+        for kernel in KERNELS {
+            match kernel.support(r) {
+                Some(support) => {
+                    assert_eq!(support, 6.0);
+                    assert_eq!(kernel.value(r, 0.0), 1.0);
+                    assert!(kernel.value(r, (support - 1e-3).powi(2)) < 1e-4);
+                    assert_eq!(kernel.value(r, support * support), 0.0);
+                    assert_eq!(kernel.value(r, (support + 1.0).powi(2)), 0.0);
+                }
+                None => assert!(kernel.value(r, 400.0) > 0.0),
+            }
+        }
+        assert_eq!(Kernel::Blinn { blobbiness: 0.5 }.value(r, 0.0), 1.0);
+        // the two Nishimura pieces meet at a third of the support
+        let nishimura = Kernel::Nishimura { support: 2.0 };
+        let a = nishimura.value(r, (2.0f32 - 1e-4).powi(2));
+        let b = nishimura.value(r, (2.0f32 + 1e-4).powi(2));
+        assert!((a - b).abs() < 1e-3);
+    }
+
+    #[test]
+    fn weight_scales_and_carves() {
+        let pos = Vec2::new(1.0, 2.0);
+        let kernel = Kernel::Wyvill { support: 2.0 };
+        let full = Ball::calc(&pos, 3.0, &kernel, 1.0, 2.0, 4.0);
+        assert!(full > 0.0);
+        assert_eq!(Ball::calc(&pos, 3.0, &kernel, 0.5, 2.0, 4.0), 0.5 * full);
+        assert_eq!(Ball::calc(&pos, 3.0, &kernel, -1.0, 2.0, 4.0), -full);
+        let in_plane = Ball::calc_3d(&pos.extend(0.0), 3.0, &kernel, 1.0, 2.0, 4.0, 0.0);
+        assert_eq!(in_plane, full);
     }
 }
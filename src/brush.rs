@@ -0,0 +1,392 @@
+use bevy::prelude::*;
+
+use crate::value_plain::ValuePlain;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    // the demo editor paints with round brushes only
+    #[allow(dead_code)]
+    Square,
+}
+
+/// Strength over the distance to the brush center, from full at the
+/// center to zero at the radius
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Falloff {
+    /// Full strength up to the radius
+    // the demo editor paints with a smooth falloff
+    #[allow(dead_code)]
+    Constant,
+    #[default]
+    Linear,
+    /// Smoothstep, soft at both ends
+    Smooth,
+}
+
+impl Falloff {
+    fn weight(&self, t: f32) -> f32 {
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BrushOp {
+    Add,
+    Subtract,
+    /// Moves values towards the given value
+    // the demo editor only adds and subtracts
+    #[allow(dead_code)]
+    Set(f32),
+    /// Moves values towards the average of their neighbours
+    Smooth,
+    /// Moves values towards the average under the brush
+    Flatten,
+}
+
+/// Inclusive range of sample indices changed by a brush stroke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub min: UVec2,
+    pub max: UVec2,
+}
+
+impl DirtyRect {
+    pub fn union(&self, other: &DirtyRect) -> DirtyRect {
+        DirtyRect {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn contains(&self, i: u32, j: u32) -> bool {
+        (self.min.x..=self.max.x).contains(&i) && (self.min.y..=self.max.y).contains(&j)
+    }
+}
+
+/// Event sent after a brush changed the samples in the rect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlainEdited(pub DirtyRect);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    /// World units
+    pub radius: f32,
+    /// Change per application at the center, for `Set`, `Smooth` and
+    /// `Flatten` the share of the way to the target
+    pub strength: f32,
+    pub falloff: Falloff,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            shape: BrushShape::default(),
+            radius: 5.0,
+            strength: 0.1,
+            falloff: Falloff::default(),
+        }
+    }
+}
+
+impl Brush {
+    pub fn new(radius: f32, strength: f32) -> Self {
+        Self {
+            radius,
+            strength,
+            ..Default::default()
+        }
+    }
+
+    // the demo editor paints with round brushes only
+    #[allow(dead_code)]
+    pub fn with_shape(mut self, shape: BrushShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    /// Brush weight of a world point, zero outside of the brush
+    pub fn weight(&self, center: Vec2, point: Vec2) -> f32 {
+        let d = (point - center).abs();
+        let distance = match self.shape {
+            BrushShape::Circle => d.length(),
+            BrushShape::Square => d.max_element(),
+        };
+        if distance > self.radius || self.radius <= 0.0 {
+            return 0.0;
+        }
+        self.strength * self.falloff.weight(distance / self.radius)
+    }
+
+    /// Samples within reach of the brush, `None` when it misses the plain
+    fn bounds(&self, plain: &ValuePlain, center: Vec2) -> Option<DirtyRect> {
+        let g1 = plain.grid_coords(center - Vec2::splat(self.radius));
+        let g2 = plain.grid_coords(center + Vec2::splat(self.radius));
+        let last = Vec2::new(plain.width as f32 - 1.0, plain.height as f32 - 1.0);
+        let min = g1.min(g2).ceil().max(Vec2::ZERO);
+        let max = g1.max(g2).floor().min(last);
+        if min.x > max.x || min.y > max.y {
+            return None;
+        }
+        Some(DirtyRect {
+            min: min.as_uvec2(),
+            max: max.as_uvec2(),
+        })
+    }
+
+    /// Applies one dab at a world point and returns the changed samples
+    pub fn apply(&self, plain: &mut ValuePlain, center: Vec2, op: BrushOp) -> Option<DirtyRect> {
+        let rect = self.bounds(plain, center)?;
+        let samples = || {
+            (rect.min.y..=rect.max.y)
+                .flat_map(move |j| (rect.min.x..=rect.max.x).map(move |i| (i, j)))
+        };
+
+        let flatten_target = if op == BrushOp::Flatten {
+            let (sum, total) = samples().fold((0.0, 0.0), |(sum, total), (i, j)| {
+                let index = plain.index(i, j);
+                let w = self.weight(center, plain.positions[index].truncate());
+                (sum + plain.values[index] * w, total + w)
+            });
+            if total <= 0.0 {
+                return None;
+            }
+            sum / total
+        } else {
+            0.0
+        };
+        // smoothing reads the values from before the dab, copied for the
+        // rect and the one sample around it that the average reaches
+        let source_min = UVec2::new(rect.min.x.saturating_sub(1), rect.min.y.saturating_sub(1));
+        let source_max = (rect.max + UVec2::ONE).min(UVec2::new(plain.width, plain.height) - 1);
+        let source_width = source_max.x - source_min.x + 1;
+        let source = if op == BrushOp::Smooth {
+            Some(
+                (source_min.y..=source_max.y)
+                    .flat_map(|j| {
+                        let row = plain.index(source_min.x, j);
+                        plain.values[row..=row + (source_width - 1) as usize]
+                            .iter()
+                            .copied()
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            None
+        };
+
+        for (i, j) in samples() {
+            let index = plain.index(i, j);
+            let w = self.weight(center, plain.positions[index].truncate());
+            if w == 0.0 {
+                continue;
+            }
+            let value = &mut plain.values[index];
+            *value = match op {
+                BrushOp::Add => *value + w,
+                BrushOp::Subtract => *value - w,
+                BrushOp::Set(target) => *value + (target - *value) * w.min(1.0),
+                BrushOp::Flatten => *value + (flatten_target - *value) * w.min(1.0),
+                BrushOp::Smooth => {
+                    let source = source.as_ref().unwrap();
+                    let (mut sum, mut count) = (0.0, 0.0);
+                    for nj in j.saturating_sub(1)..=(j + 1).min(source_max.y) {
+                        for ni in i.saturating_sub(1)..=(i + 1).min(source_max.x) {
+                            let (si, sj) = (ni - source_min.x, nj - source_min.y);
+                            sum += source[(si + sj * source_width) as usize];
+                            count += 1.0;
+                        }
+                    }
+                    *value + (sum / count - *value) * w.min(1.0)
+                }
+            };
+        }
+        Some(rect)
+    }
+
+    /// Dabs along a segment about every half radius, for dragging
+    pub fn stroke(
+        &self,
+        plain: &mut ValuePlain,
+        from: Vec2,
+        to: Vec2,
+        op: BrushOp,
+    ) -> Option<DirtyRect> {
+        let spacing = (self.radius * 0.5).max(f32::EPSILON);
+        let steps = (from.distance(to) / spacing).ceil().max(1.0) as u32;
+        (0..=steps)
+            .filter_map(|s| self.apply(plain, from.lerp(to, s as f32 / steps as f32), op))
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp_plain() -> ValuePlain {
+        let mut plain = ValuePlain::new(16, 12);
+        plain.update(&|x, y| 0.1 * x - 0.05 * y);
+        plain
+    }
+
+    #[test]
+    fn rect_covers_exactly_the_samples_in_reach() {
+        let plain = ValuePlain::new(16, 12);
+        let brush = Brush::new(2.6, 1.0).with_falloff(Falloff::Constant);
+        let center = Vec2::new(1.2, -0.7);
+        let rect = brush.bounds(&plain, center).unwrap();
+        for j in 0..plain.height {
+            for i in 0..plain.width {
+                let p = plain.positions[plain.index(i, j)].truncate();
+                let in_reach = (p - center).abs().max_element() <= brush.radius;
+                assert_eq!(rect.contains(i, j), in_reach, "{} {}", i, j);
+            }
+        }
+        // clipped to the plain along the border, missing it entirely
+        let corner = brush.bounds(&plain, Vec2::new(-8.0, 6.0)).unwrap();
+        assert_eq!(corner.min, UVec2::ZERO);
+        assert_eq!(corner.max, UVec2::new(2, 2));
+        assert_eq!(brush.bounds(&plain, Vec2::new(20.0, 0.0)), None);
+    }
+
+    #[test]
+    fn union_and_contains() {
+        let a = DirtyRect {
+            min: UVec2::new(1, 4),
+            max: UVec2::new(3, 5),
+        };
+        let b = DirtyRect {
+            min: UVec2::new(6, 2),
+            max: UVec2::new(7, 3),
+        };
+        let union = a.union(&b);
+        assert_eq!(union.min, UVec2::new(1, 2));
+        assert_eq!(union.max, UVec2::new(7, 5));
+        assert!(a.contains(1, 5) && a.contains(3, 4));
+        assert!(!a.contains(0, 4) && !a.contains(2, 6));
+    }
+
+    #[test]
+    fn falloffs_and_shapes() {
+        let center = Vec2::ZERO;
+        let half = Vec2::new(1.0, 0.0);
+        let brush = Brush::new(2.0, 0.5);
+        assert_eq!(brush.weight(center, center), 0.5);
+        assert_eq!(brush.weight(center, half), 0.25);
+        let smooth = brush.with_falloff(Falloff::Smooth);
+        assert_eq!(smooth.weight(center, half), 0.25);
+        assert!(smooth.weight(center, Vec2::new(0.5, 0.0)) > 0.25 * 1.5);
+        let constant = brush.with_falloff(Falloff::Constant);
+        assert_eq!(constant.weight(center, Vec2::new(2.0, 0.0)), 0.5);
+        // the square reaches into the corners the circle leaves out
+        let corner = Vec2::new(1.8, 1.8);
+        assert_eq!(constant.weight(center, corner), 0.0);
+        let square = constant.with_shape(BrushShape::Square);
+        assert_eq!(square.weight(center, corner), 0.5);
+    }
+
+    #[test]
+    fn add_and_subtract_only_touch_the_rect() {
+        let original = ramp_plain();
+        let brush = Brush::new(3.0, 0.2);
+        let center = Vec2::new(-2.0, 1.5);
+        let mut plain = original.clone();
+        let rect = brush.apply(&mut plain, center, BrushOp::Add).unwrap();
+        for j in 0..plain.height {
+            for i in 0..plain.width {
+                let index = plain.index(i, j);
+                let p = plain.positions[index].truncate();
+                let expected = original.values[index] + brush.weight(center, p);
+                assert!((plain.values[index] - expected).abs() < 1e-6);
+                if !rect.contains(i, j) {
+                    assert_eq!(plain.values[index], original.values[index]);
+                }
+            }
+        }
+        let back = brush.apply(&mut plain, center, BrushOp::Subtract);
+        assert_eq!(back, Some(rect));
+        for (a, b) in plain.values.iter().zip(original.values.iter()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn set_and_flatten_with_full_strength() {
+        let brush = Brush::new(3.0, 1.0).with_falloff(Falloff::Constant);
+        let center = Vec2::new(0.5, 0.5);
+        let inside = |plain: &ValuePlain| {
+            let positions = plain.positions.clone();
+            plain
+                .values
+                .clone()
+                .into_iter()
+                .zip(positions)
+                .filter(move |(_, p)| p.truncate().distance(center) <= 3.0)
+                .map(|(v, _)| v)
+        };
+
+        let mut plain = ramp_plain();
+        brush.apply(&mut plain, center, BrushOp::Set(0.7));
+        assert!(inside(&plain).all(|v| (v - 0.7).abs() < 1e-6));
+
+        let mut plain = ramp_plain();
+        let average = inside(&plain).sum::<f32>() / inside(&plain).count() as f32;
+        brush.apply(&mut plain, center, BrushOp::Flatten);
+        assert!(inside(&plain).all(|v| (v - average).abs() < 1e-6));
+    }
+
+    #[test]
+    fn smooth_averages_the_values_from_before_the_dab() {
+        let mut original = ValuePlain::new(16, 12);
+        original.update(&|x, y| ((x * 1.7).sin() + (y * 2.3).cos()) * 0.5);
+        let brush = Brush::new(3.0, 1.0).with_falloff(Falloff::Constant);
+        // in the middle and clipped by the corner of the plain
+        for center in [Vec2::new(0.2, 0.9), Vec2::new(7.0, -5.5)] {
+            let mut plain = original.clone();
+            let rect = brush.apply(&mut plain, center, BrushOp::Smooth).unwrap();
+            for j in rect.min.y..=rect.max.y {
+                for i in rect.min.x..=rect.max.x {
+                    let index = plain.index(i, j);
+                    if brush.weight(center, plain.positions[index].truncate()) == 0.0 {
+                        continue;
+                    }
+                    let (mut sum, mut count) = (0.0, 0.0);
+                    for nj in j.saturating_sub(1)..=(j + 1).min(plain.height - 1) {
+                        for ni in i.saturating_sub(1)..=(i + 1).min(plain.width - 1) {
+                            sum += original.values[original.index(ni, nj)];
+                            count += 1.0;
+                        }
+                    }
+                    assert!((plain.values[index] - sum / count).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_unions_its_dabs() {
+        let brush = Brush::new(2.0, 0.1);
+        let (from, to) = (Vec2::new(-5.0, -3.0), Vec2::new(4.0, 2.0));
+        let mut plain = ramp_plain();
+        let rect = brush.stroke(&mut plain, from, to, BrushOp::Add).unwrap();
+        let start = brush.bounds(&plain, from).unwrap();
+        let end = brush.bounds(&plain, to).unwrap();
+        assert_eq!(rect, start.union(&end));
+        // dabs no further apart than half the radius leave no gaps
+        let middle = plain.grid_coords(from.lerp(to, 0.5)).round().as_uvec2();
+        let index = plain.index(middle.x, middle.y);
+        assert!(plain.values[index] > ramp_plain().values[index] + 0.1);
+    }
+}
@@ -0,0 +1,227 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::value_plain::ValuePlain;
+
+/// Life-like birth and survival rule over the eight neighbours, bit `n` is
+/// set when `n` alive neighbours apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub birth: u16,
+    pub survival: u16,
+}
+
+impl Default for Rule {
+    /// B678/S345678, grows solid walls around open caves
+    fn default() -> Self {
+        Self::parse("B678/S345678").unwrap()
+    }
+}
+
+impl Rule {
+    /// Reads rules written like "B678/S345678"
+    pub fn parse(rule: &str) -> Option<Self> {
+        let (birth, survival) = rule.split_once('/')?;
+        let counts = |part: &str, prefix: char| {
+            part.strip_prefix(prefix)?
+                .chars()
+                .try_fold(0u16, |mask, c| {
+                    let n = c.to_digit(10).filter(|n| *n <= 8)?;
+                    Some(mask | 1 << n)
+                })
+        };
+        Some(Self {
+            birth: counts(birth, 'B')?,
+            survival: counts(survival, 'S')?,
+        })
+    }
+
+    pub fn next(&self, alive: bool, neighbours: u32) -> bool {
+        let mask = if alive { self.survival } else { self.birth };
+        mask & (1 << neighbours) != 0
+    }
+}
+
+/// Random fill followed by cellular automaton steps and a blur. Alive
+/// cells end up near 1 and dead ones near 0, so a layer at 0.5 outlines
+/// the walls.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CaveGenerator {
+    pub rule: Rule,
+    /// Share of cells alive after the random fill
+    pub fill: f32,
+    pub seed: u64,
+    pub iterations: u32,
+    /// Box blur passes at the end
+    pub blur: u32,
+    /// Samples outside of the plain count as alive, which closes the caves
+    /// along the border
+    pub solid_border: bool,
+}
+
+impl Default for CaveGenerator {
+    fn default() -> Self {
+        Self {
+            rule: Rule::default(),
+            fill: 0.45,
+            seed: 0,
+            iterations: 5,
+            blur: 2,
+            solid_border: true,
+        }
+    }
+}
+
+impl CaveGenerator {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn generate(&self, plain: &mut ValuePlain) {
+        let (width, height) = (plain.width as i64, plain.height as i64);
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let mut cells = (0..plain.values.len())
+            .map(|_| rng.gen::<f32>() < self.fill)
+            .collect::<Vec<_>>();
+
+        let mut next = cells.clone();
+        for _ in 0..self.iterations {
+            for j in 0..height {
+                for i in 0..width {
+                    let mut neighbours = 0;
+                    for (di, dj) in NEIGHBOURS {
+                        let (ni, nj) = (i + di, j + dj);
+                        let alive = if ni < 0 || nj < 0 || ni >= width || nj >= height {
+                            self.solid_border
+                        } else {
+                            cells[(ni + nj * width) as usize]
+                        };
+                        neighbours += alive as u32;
+                    }
+                    let index = (i + j * width) as usize;
+                    next[index] = self.rule.next(cells[index], neighbours);
+                }
+            }
+            std::mem::swap(&mut cells, &mut next);
+        }
+
+        for (v, alive) in plain.values.iter_mut().zip(cells.iter()) {
+            *v = if *alive { 1.0 } else { 0.0 };
+        }
+        let border = if self.solid_border { Some(1.0) } else { None };
+        for _ in 0..self.blur {
+            blur(plain, border);
+        }
+    }
+}
+
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// 3x3 box blur, samples outside of the plain take `border` or are left
+/// out when it is `None`
+fn blur(plain: &mut ValuePlain, border: Option<f32>) {
+    let (width, height) = (plain.width as i64, plain.height as i64);
+    let source = plain.values.clone();
+    for j in 0..height {
+        for i in 0..width {
+            let (mut sum, mut count) = (source[(i + j * width) as usize], 1.0);
+            for (di, dj) in NEIGHBOURS {
+                let (ni, nj) = (i + di, j + dj);
+                let value = if ni < 0 || nj < 0 || ni >= width || nj >= height {
+                    border
+                } else {
+                    Some(source[(ni + nj * width) as usize])
+                };
+                if let Some(v) = value {
+                    sum += v;
+                    count += 1.0;
+                }
+            }
+            plain.values[(i + j * width) as usize] = sum / count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rules() {
+        let rule = Rule::parse("B678/S345678").unwrap();
+        assert_eq!(rule.birth, 0b1_1100_0000);
+        assert_eq!(rule.survival, 0b1_1111_1000);
+        assert_eq!(rule, Rule::default());
+        assert!(rule.next(false, 6));
+        assert!(!rule.next(false, 5));
+        assert!(rule.next(true, 3));
+        assert!(!rule.next(true, 2));
+        // Conway's life, with an empty birth part
+        assert_eq!(Rule::parse("B3/S23").unwrap().survival, 0b1100);
+        assert_eq!(Rule::parse("B/S23").unwrap().birth, 0);
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        for rule in [
+            "",
+            "B678",
+            "678/345678",
+            "S345678/B678",
+            "B9/S3",
+            "B6x/S3",
+            "B6/S3/S4",
+        ] {
+            assert_eq!(Rule::parse(rule), None, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_cave() {
+        let generator = CaveGenerator::default().with_seed(3);
+        let mut a = ValuePlain::new(24, 16);
+        let mut b = ValuePlain::new(24, 16);
+        generator.generate(&mut a);
+        generator.generate(&mut b);
+        assert_eq!(a.values, b.values);
+        assert!(a.values.iter().all(|v| (0.0..=1.0).contains(v)));
+
+        let mut other = ValuePlain::new(24, 16);
+        CaveGenerator::default().with_seed(4).generate(&mut other);
+        assert_ne!(a.values, other.values);
+    }
+
+    #[test]
+    fn without_blur_cells_are_zero_or_one() {
+        let mut plain = ValuePlain::new(24, 16);
+        CaveGenerator {
+            blur: 0,
+            ..Default::default()
+        }
+        .generate(&mut plain);
+        assert!(plain.values.iter().all(|v| *v == 0.0 || *v == 1.0));
+        assert!(plain.values.contains(&0.0) && plain.values.contains(&1.0));
+    }
+
+    #[test]
+    fn full_fill_stays_solid() {
+        let mut plain = ValuePlain::new(12, 12);
+        CaveGenerator {
+            fill: 1.0,
+            ..Default::default()
+        }
+        .generate(&mut plain);
+        assert!(plain.values.iter().all(|v| *v == 1.0));
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorRamp {
+    stops: Vec<(f32, Color)>,
+}
+
+impl ColorRamp {
+    pub fn new(mut stops: Vec<(f32, Color)>) -> Self {
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    pub fn sample(&self, value: f32) -> Color {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Color::WHITE,
+        };
+        if value <= first.0 {
+            return first.1;
+        }
+        if value >= last.0 {
+            return last.1;
+        }
+        let next = self.stops.iter().position(|s| s.0 > value).unwrap();
+        let (v_1, c_1) = self.stops[next - 1];
+        let (v_2, c_2) = self.stops[next];
+        let t = (value - v_1) / (v_2 - v_1);
+
+        let c_1 = Vec4::from(c_1.as_linear_rgba_f32());
+        let c_2 = Vec4::from(c_2.as_linear_rgba_f32());
+        let c = c_1.lerp(c_2, t);
+        Color::rgba_linear(c.x, c.y, c.z, c.w)
+    }
+
+    pub fn vertex_colors(&self, values: &[f32]) -> Vec<u32> {
+        values
+            .iter()
+            .map(|v| self.sample(*v).as_linear_rgba_u32())
+            .collect()
+    }
+}
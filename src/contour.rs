@@ -0,0 +1,355 @@
+use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::simplify::{self, Simplification};
+use crate::smooth::{self, Smoothing};
+use crate::value_plain::ValuePlain;
+
+/// Polyline along the threshold. Closed contours do not repeat their first
+/// point at the end.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Contour {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+}
+
+impl Contour {
+    pub fn segments(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let n = self.points.len();
+        let count = if self.closed { n } else { n.saturating_sub(1) };
+        (0..count).map(move |i| (self.points[i], self.points[(i + 1) % n]))
+    }
+}
+
+/// Post processing applied to contours before they are triangulated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContourFilter {
+    Simplify {
+        method: Simplification,
+        tolerance: f32,
+    },
+    Smooth(Smoothing),
+}
+
+impl ContourFilter {
+    pub fn apply(&self, contours: &[Contour]) -> Vec<Contour> {
+        match *self {
+            ContourFilter::Simplify { method, tolerance } => {
+                simplify::simplify(contours, method, tolerance)
+            }
+            ContourFilter::Smooth(smoothing) => smooth::smooth(contours, smoothing),
+        }
+    }
+}
+
+/// Grid coordinates of a sample, samples outside of the plain included
+type Sample = (i64, i64);
+/// Cell edge given by its two samples, the smaller one first
+type Edge = (Sample, Sample);
+/// Point where a contour crosses a cell edge. Crossings are told apart by
+/// their edge and not by their position, samples exactly at the threshold
+/// put the crossings of several edges on the same point.
+type Crossing = (Edge, Vec2);
+
+/// Contour segments of `threshold` over the whole plain. Every segment
+/// has the inside (values above the threshold) on its left.
+pub fn segments(plain: &ValuePlain, threshold: f32) -> Vec<(Vec2, Vec2)> {
+    let mut segments = Vec::new();
+    for j in 0..(plain.height - 1) {
+        for i in 0..(plain.width - 1) {
+            cell_segments(plain, threshold, i, j, &mut segments);
+        }
+    }
+    segments
+}
+
+/// Contours chained from `segments`, regions touching the border of the
+/// plain give open contours
+// the layers mesh `closed_contours`, only the polygon tests close open
+// contours along the border with `polygon::group`
+#[allow(dead_code)]
+pub fn contours(plain: &ValuePlain, threshold: f32) -> Vec<Contour> {
+    let mut segments = Vec::new();
+    for j in 0..(plain.height - 1) {
+        for i in 0..(plain.width - 1) {
+            corner_segments(&grid_corners(plain, i, j), threshold, &mut segments);
+        }
+    }
+    chain(&segments)
+}
+
+/// Same as `contours`, but everything outside of the plain counts as
+/// outside, so regions touching the border are closed along it
+pub fn closed_contours(plain: &ValuePlain, threshold: f32) -> Vec<Contour> {
+    let width = plain.width as i64;
+    let height = plain.height as i64;
+    let sample = |i: i64, j: i64| {
+        let index = plain.index(i.clamp(0, width - 1) as u32, j.clamp(0, height - 1) as u32);
+        let value = if (0..width).contains(&i) && (0..height).contains(&j) {
+            plain.values[index]
+        } else {
+            f32::NEG_INFINITY
+        };
+        ((i, j), plain.positions[index].truncate(), value)
+    };
+
+    let mut segments = Vec::new();
+    for j in -1..height {
+        for i in -1..width {
+            let corners =
+                [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)].map(|(ci, cj)| sample(ci, cj));
+            corner_segments(&corners, threshold, &mut segments);
+        }
+    }
+    chain(&segments)
+}
+
+/// Corners of the cell in counter clockwise order:
+/// top left, bottom left, bottom right, top right
+pub fn cell_corners(plain: &ValuePlain, i: u32, j: u32) -> [usize; 4] {
+    [
+        (i + j * plain.width) as usize,
+        (i + (j + 1) * plain.width) as usize,
+        (i + 1 + (j + 1) * plain.width) as usize,
+        (i + 1 + j * plain.width) as usize,
+    ]
+}
+
+fn grid_corners(plain: &ValuePlain, i: u32, j: u32) -> [(Sample, Vec2, f32); 4] {
+    let (i, j) = (i as i64, j as i64);
+    [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)].map(|(ci, cj)| {
+        let index = plain.index(ci as u32, cj as u32);
+        (
+            (ci, cj),
+            plain.positions[index].truncate(),
+            plain.values[index],
+        )
+    })
+}
+
+pub fn crossing(plain: &ValuePlain, threshold: f32, p1: usize, p2: usize) -> Vec2 {
+    interpolate(
+        (plain.positions[p1].truncate(), plain.values[p1]),
+        (plain.positions[p2].truncate(), plain.values[p2]),
+        threshold,
+    )
+}
+
+fn interpolate((pos_1, val_1): (Vec2, f32), (pos_2, val_2): (Vec2, f32), threshold: f32) -> Vec2 {
+    // samples outside of the plain are at minus infinity
+    if val_1 == f32::NEG_INFINITY {
+        return pos_2;
+    }
+    if val_2 == f32::NEG_INFINITY {
+        return pos_1;
+    }
+    let t = ((threshold - val_1) / (val_2 - val_1)).clamp(0.0, 1.0);
+    pos_1.lerp(pos_2, t)
+}
+
+pub fn cell_segments(
+    plain: &ValuePlain,
+    threshold: f32,
+    i: u32,
+    j: u32,
+    segments: &mut Vec<(Vec2, Vec2)>,
+) {
+    let mut crossings = Vec::with_capacity(2);
+    corner_segments(&grid_corners(plain, i, j), threshold, &mut crossings);
+    segments.extend(crossings.into_iter().map(|((_, a), (_, b))| (a, b)));
+}
+
+fn corner_segments(
+    corners: &[(Sample, Vec2, f32); 4],
+    threshold: f32,
+    segments: &mut Vec<(Crossing, Crossing)>,
+) {
+    let inside = corners.map(|c| c.2 > threshold);
+
+    // (crossing, leaving the inside) for every crossed edge, in corner order
+    let mut crossings = Vec::with_capacity(4);
+    for k in 0..4 {
+        let k2 = (k + 1) % 4;
+        if inside[k] != inside[k2] {
+            let (s_1, pos_1, val_1) = corners[k];
+            let (s_2, pos_2, val_2) = corners[k2];
+            let point = interpolate((pos_1, val_1), (pos_2, val_2), threshold);
+            crossings.push(((s_1.min(s_2), s_1.max(s_2)), point, inside[k]));
+        }
+    }
+    if crossings.is_empty() {
+        return;
+    }
+
+    // saddles are resolved with the value in the middle of the cell
+    let center = corners.iter().map(|c| c.2).sum::<f32>() * 0.25;
+    let connect = crossings.len() == 4 && center > threshold;
+
+    let n = crossings.len();
+    for k in 0..n {
+        let (edge, point, leaving) = crossings[k];
+        if !leaving {
+            continue;
+        }
+        let (end_edge, end_point, _) = if connect {
+            crossings[(k + 1) % n]
+        } else {
+            crossings[(k + n - 1) % n]
+        };
+        segments.push(((edge, point), (end_edge, end_point)));
+    }
+}
+
+fn key(v: Vec2) -> (i64, i64) {
+    ((v.x * 1024.0).round() as i64, (v.y * 1024.0).round() as i64)
+}
+
+/// Joins segments sharing a crossed edge into polylines. Every edge starts
+/// at most one segment and ends at most one, so the chaining is exact even
+/// where crossings coincide. Repeated points are dropped.
+fn chain(segments: &[(Crossing, Crossing)]) -> Vec<Contour> {
+    let next = segments
+        .iter()
+        .enumerate()
+        .map(|(i, ((a, _), _))| (*a, i))
+        .collect::<HashMap<_, _>>();
+    let ends = segments
+        .iter()
+        .map(|(_, (b, _))| *b)
+        .collect::<HashSet<_>>();
+
+    let push = |points: &mut Vec<Vec2>, point: Vec2| {
+        if points.last().is_none_or(|last| key(*last) != key(point)) {
+            points.push(point);
+        }
+    };
+    let mut used = vec![false; segments.len()];
+    let mut contours = Vec::new();
+    let follow = |first: usize, used: &mut Vec<bool>| {
+        let mut points = vec![segments[first].0 .1];
+        let mut current = first;
+        loop {
+            used[current] = true;
+            let (end_edge, end) = segments[current].1;
+            match next.get(&end_edge) {
+                Some(n) if !used[*n] => {
+                    push(&mut points, end);
+                    current = *n;
+                }
+                Some(n) if *n == first => {
+                    if points.len() > 1 && key(points[0]) == key(*points.last().unwrap()) {
+                        points.pop();
+                    }
+                    return Contour {
+                        points,
+                        closed: true,
+                    };
+                }
+                _ => {
+                    push(&mut points, end);
+                    return Contour {
+                        points,
+                        closed: false,
+                    };
+                }
+            }
+        }
+    };
+
+    // open contours start where no other segment ends
+    for (i, ((a, _), _)) in segments.iter().enumerate() {
+        if !used[i] && !ends.contains(a) {
+            contours.push(follow(i, &mut used));
+        }
+    }
+    for i in 0..segments.len() {
+        if !used[i] {
+            contours.push(follow(i, &mut used));
+        }
+    }
+    contours
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polygon;
+
+    /// Hashed values in {-1, 0, 1}, so a third of the samples sit exactly
+    /// on a threshold of zero
+    fn terraced_plain() -> ValuePlain {
+        let mut plain = ValuePlain::new(16, 12);
+        for j in 0..plain.height {
+            for i in 0..plain.width {
+                let mut h = (i.wrapping_mul(73856093) ^ j.wrapping_mul(19349663) ^ 83492791)
+                    .wrapping_mul(2654435761);
+                h ^= h >> 15;
+                let index = plain.index(i, j);
+                plain.values[index] = (h % 3) as f32 - 1.0;
+            }
+        }
+        plain
+    }
+
+    /// Inside area of every cell, clipped like `corner_segments` does it
+    fn expected_area(plain: &ValuePlain, threshold: f32) -> f32 {
+        let mut area = 0.0;
+        for j in 0..(plain.height - 1) {
+            for i in 0..(plain.width - 1) {
+                let corners = cell_corners(plain, i, j)
+                    .map(|c| (plain.positions[c].truncate(), plain.values[c]));
+                let inside = corners.map(|c| c.1 > threshold);
+                let crossing = |k: usize| interpolate(corners[k], corners[(k + 1) % 4], threshold);
+                let crossed = (0..4).filter(|k| inside[*k] != inside[(k + 1) % 4]).count();
+                let center = corners.iter().map(|c| c.1).sum::<f32>() * 0.25;
+                if crossed == 4 && center <= threshold {
+                    // two separate corner triangles
+                    for k in (0..4).filter(|k| inside[*k]) {
+                        let triangle = [crossing((k + 3) % 4), corners[k].0, crossing(k)];
+                        area += polygon::signed_area(&triangle);
+                    }
+                    continue;
+                }
+                let mut points = Vec::new();
+                for k in 0..4 {
+                    if inside[k] {
+                        points.push(corners[k].0);
+                    }
+                    if inside[k] != inside[(k + 1) % 4] {
+                        points.push(crossing(k));
+                    }
+                }
+                area += polygon::signed_area(&points);
+            }
+        }
+        area
+    }
+
+    #[test]
+    fn samples_at_the_threshold_give_closed_contours() {
+        let plain = terraced_plain();
+        let contours = closed_contours(&plain, 0.0);
+        assert!(contours.len() > 3);
+        for contour in &contours {
+            assert!(contour.closed, "open contour {:?}", contour.points);
+        }
+        let area = contours
+            .iter()
+            .map(|c| polygon::signed_area(&c.points))
+            .sum::<f32>();
+        let expected = expected_area(&plain, 0.0);
+        assert!((area - expected).abs() < 1e-3, "{} != {}", area, expected);
+    }
+
+    #[test]
+    fn open_contours_end_on_the_border() {
+        let plain = terraced_plain();
+        let (min, max) = plain.bounds();
+        for contour in contours(&plain, 0.0).iter().filter(|c| !c.closed) {
+            for p in [contour.points[0], *contour.points.last().unwrap()] {
+                let on_border = p.x == min.x || p.x == max.x || p.y == min.y || p.y == max.y;
+                assert!(on_border, "open contour ends inside at {:?}", p);
+            }
+        }
+    }
+}
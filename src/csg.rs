@@ -0,0 +1,115 @@
+/// Boolean operations on fields where higher values are inside. The
+/// subtractions flip the second field around zero, so fields with another
+/// threshold should be shifted to zero first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Csg {
+    Union,
+    Intersection,
+    Subtraction,
+    /// Union with the seam rounded over `radius`
+    SmoothUnion(f32),
+    SmoothIntersection(f32),
+    SmoothSubtraction(f32),
+}
+
+impl Csg {
+    pub fn apply(&self, a: f32, b: f32) -> f32 {
+        match *self {
+            Csg::Union => a.max(b),
+            Csg::Intersection => a.min(b),
+            Csg::Subtraction => a.min(-b),
+            Csg::SmoothUnion(radius) => smooth_max(a, b, radius),
+            Csg::SmoothIntersection(radius) => smooth_min(a, b, radius),
+            Csg::SmoothSubtraction(radius) => smooth_min(a, -b, radius),
+        }
+    }
+
+    /// Field combining two fields, can be passed to `ValuePlain::update`
+    pub fn combine(
+        self,
+        a: impl Fn(f32, f32) -> f32,
+        b: impl Fn(f32, f32) -> f32,
+    ) -> impl Fn(f32, f32) -> f32 {
+        move |x, y| self.apply(a(x, y), b(x, y))
+    }
+}
+
+/// Polynomial smooth minimum, equal to `min` once the values are more
+/// than `radius` apart
+pub fn smooth_min(a: f32, b: f32, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / radius).clamp(0.0, 1.0);
+    b + (a - b) * h - radius * h * (1.0 - h)
+}
+
+pub fn smooth_max(a: f32, b: f32, radius: f32) -> f32 {
+    -smooth_min(-a, -b, radius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value_plain::ValuePlain;
+
+    /// Circle of radius 5 around (cx, 0), positive inside
+    fn circle(cx: f32) -> impl Fn(f32, f32) -> f32 {
+        move |x, y| 5.0 - ((x - cx).powi(2) + y * y).sqrt()
+    }
+
+    #[test]
+    fn boolean_operations() {
+        let (a, b) = (circle(-3.0), circle(3.0));
+        let inside = |csg: Csg, x: f32| csg.combine(&a, &b)(x, 0.0) > 0.0;
+        // only in a, in both, only in b
+        assert_eq!([-6.0, 0.0, 6.0].map(|x| inside(Csg::Union, x)), [true; 3]);
+        assert_eq!(
+            [-6.0, 0.0, 6.0].map(|x| inside(Csg::Intersection, x)),
+            [false, true, false]
+        );
+        assert_eq!(
+            [-6.0, 0.0, 6.0].map(|x| inside(Csg::Subtraction, x)),
+            [true, false, false]
+        );
+    }
+
+    #[test]
+    fn smooth_operations_match_outside_the_radius() {
+        for (a, b) in [(0.0, 2.0), (-1.5, 3.0), (4.0, -4.0)] {
+            assert_eq!(Csg::SmoothUnion(1.0).apply(a, b), Csg::Union.apply(a, b));
+            assert_eq!(
+                Csg::SmoothIntersection(1.0).apply(a, b),
+                Csg::Intersection.apply(a, b)
+            );
+            assert_eq!(
+                Csg::SmoothSubtraction(1.0).apply(a, -b),
+                Csg::Subtraction.apply(a, -b)
+            );
+        }
+        // within the radius the seam is rounded outwards
+        assert!(Csg::SmoothUnion(1.0).apply(0.2, 0.0) > 0.2);
+        assert!(Csg::SmoothIntersection(1.0).apply(0.2, 0.0) < 0.0);
+        assert_eq!(smooth_min(0.2, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn plains_combine_per_sample() {
+        let mut a = ValuePlain::new(20, 10);
+        a.update(&circle(-3.0));
+        let mut b = ValuePlain::new(20, 10);
+        b.update(&circle(3.0));
+        let mut expected = ValuePlain::new(20, 10);
+        expected.update(&Csg::SmoothUnion(2.0).combine(circle(-3.0), circle(3.0)));
+
+        a.combine(&b, Csg::SmoothUnion(2.0));
+        assert_eq!(a.values, expected.values);
+
+        a.shift(-1.0);
+        assert!(a
+            .values
+            .iter()
+            .zip(expected.values.iter())
+            .all(|(a, e)| *a == e - 1.0));
+    }
+}
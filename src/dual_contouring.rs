@@ -0,0 +1,180 @@
+use bevy::math::Mat2;
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+
+use crate::contour;
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+use crate::threshold_layer::ThresholdLayer;
+use crate::value_plain::ValuePlain;
+
+/// Places one vertex per cell at the point that best fits the tangent
+/// lines of all edge crossings (Hermite data from the plain gradient),
+/// so sharp corners of the field survive.
+#[derive(Debug)]
+pub struct DualContouring {
+    settings: MeshSettings,
+    /// Pull towards the average of the crossings, keeps the solution stable
+    /// when all tangents are parallel
+    bias: f32,
+}
+
+impl Default for DualContouring {
+    fn default() -> Self {
+        Self {
+            settings: MeshSettings::default(),
+            bias: 0.01,
+        }
+    }
+}
+
+impl DualContouring {
+    pub fn with_settings(mut self, settings: MeshSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn mesh_from_plain(&self, plain: &ValuePlain, layer: &ThresholdLayer) -> Mesh {
+        let cells_width = plain.width - 1;
+        let cells_height = plain.height - 1;
+
+        // vertex of every cell that has at least one inside corner
+        let mut vertices = Vec::new();
+        let mut values = Vec::new();
+        let mut indices = Vec::new();
+        let mut cell_vertices = vec![0; (cells_width * cells_height) as usize];
+        for j in 0..cells_height {
+            for i in 0..cells_width {
+                if let Some((position, value)) = self.cell_vertex(plain, layer, i, j) {
+                    vertices.push([position.x, position.y, 0.0]);
+                    values.push(value);
+                    cell_vertices[(i + j * cells_width) as usize] = vertices.len() as u32 - 1;
+                }
+            }
+        }
+
+        // every inside point is covered by the face joining the vertices of
+        // the four cells around it, the outer edges of those faces make the
+        // contour
+        for j in 1..(plain.height - 1) {
+            for i in 1..(plain.width - 1) {
+                if !layer.normalized_values[plain.index(i, j)] {
+                    continue;
+                }
+                // counter clockwise: top left, bottom left, bottom right, top right
+                let face = [(i - 1, j - 1), (i - 1, j), (i, j), (i, j - 1)]
+                    .map(|(ci, cj)| cell_vertices[(ci + cj * cells_width) as usize]);
+
+                let position = |v: u32| Vec2::from_slice(&vertices[v as usize][..2]);
+                let diagonal_1 = position(face[0]).distance_squared(position(face[2]));
+                let diagonal_2 = position(face[1]).distance_squared(position(face[3]));
+                if diagonal_1 <= diagonal_2 {
+                    indices.extend([face[0], face[1], face[2], face[0], face[2], face[3]]);
+                } else {
+                    indices.extend([face[1], face[2], face[3], face[1], face[3], face[0]]);
+                }
+            }
+        }
+
+        let mut mesh = build_mesh(vertices, indices, None, &self.settings, plain.bounds());
+        if let Some(ramp) = &self.settings.color_ramp {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, ramp.vertex_colors(&values));
+        }
+        mesh
+    }
+
+    fn cell_vertex(
+        &self,
+        plain: &ValuePlain,
+        layer: &ThresholdLayer,
+        i: u32,
+        j: u32,
+    ) -> Option<(Vec2, f32)> {
+        let corners = contour::cell_corners(plain, i, j);
+        let inside = corners.map(|c| layer.normalized_values[c]);
+        if !inside.iter().any(|v| *v) {
+            return None;
+        }
+        let min = plain.positions[corners[1]].truncate();
+        let max = plain.positions[corners[3]].truncate();
+        if inside.iter().all(|v| *v) {
+            let value = corners.iter().map(|c| plain.values[*c]).sum::<f32>() * 0.25;
+            return Some(((min + max) * 0.5, value));
+        }
+
+        let grid = [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)];
+        // normal equations of the quadratic error function
+        let mut ata = Mat2::ZERO;
+        let mut atb = Vec2::ZERO;
+        let mut mass_point = Vec2::ZERO;
+        let mut crossings = 0.0;
+        for k in 0..4 {
+            let k2 = (k + 1) % 4;
+            if inside[k] == inside[k2] {
+                continue;
+            }
+            let point = contour::crossing(plain, layer.threshold, corners[k], corners[k2]);
+            let val_1 = plain.values[corners[k]];
+            let val_2 = plain.values[corners[k2]];
+            let t = ((layer.threshold - val_1) / (val_2 - val_1)).clamp(0.0, 1.0);
+            let normal = plain
+                .gradient(grid[k].0, grid[k].1)
+                .lerp(plain.gradient(grid[k2].0, grid[k2].1), t)
+                .normalize_or_zero();
+
+            ata += Mat2::from_cols(normal * normal.x, normal * normal.y);
+            atb += normal * normal.dot(point);
+            mass_point += point;
+            crossings += 1.0;
+        }
+        mass_point /= crossings;
+
+        ata += Mat2::from_diagonal(Vec2::splat(self.bias));
+        atb += mass_point * self.bias;
+        let position = if ata.determinant().abs() > f32::EPSILON {
+            ata.inverse() * atb
+        } else {
+            mass_point
+        };
+        Some((position.clamp(min, max), layer.threshold))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square from -3 to 3, the field is the distance to the nearest side
+    fn box_layer() -> (ValuePlain, ThresholdLayer) {
+        let mut plain = ValuePlain::new(12, 12);
+        plain.update(&|x, y| (3.0 - x.abs()).min(3.0 - y.abs()));
+        let mut layer = ThresholdLayer::new(12, 12, 0.0);
+        layer.update_values(&plain);
+        (plain, layer)
+    }
+
+    #[test]
+    fn vertex_lands_on_the_box_side() {
+        let (plain, layer) = box_layer();
+        // the cell from (2.5, -0.5) to (3.5, 0.5)
+        let (vertex, value) = DualContouring::default()
+            .cell_vertex(&plain, &layer, 8, 5)
+            .unwrap();
+        assert!((vertex.x - 3.0).abs() < 1e-4, "{}", vertex);
+        assert!(vertex.y.abs() < 1e-4, "{}", vertex);
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn vertex_is_pulled_into_the_box_corner() {
+        let (plain, layer) = box_layer();
+        // the cell from (2.5, 2.5) to (3.5, 3.5), its crossings average
+        // to (2.75, 2.75) which is where marching squares cuts the corner.
+        // The gradients are central differences across the corner, so the
+        // tangent lines are not quite axis aligned.
+        let (vertex, _) = DualContouring::default()
+            .cell_vertex(&plain, &layer, 8, 2)
+            .unwrap();
+        assert!(vertex.distance(Vec2::new(3.0, 3.0)) < 0.2, "{}", vertex);
+        assert!((vertex.x - vertex.y).abs() < 1e-4, "{}", vertex);
+    }
+}
@@ -0,0 +1,157 @@
+use bevy::prelude::*;
+use bevy::render::camera::Camera;
+use rand::Rng;
+
+use crate::ball::{Ball, Position, Radius, Veclocity};
+use crate::brush::{Brush, BrushOp, Falloff, PlainEdited};
+use crate::value_plain::ValuePlain;
+
+/// Plain the mouse paints into, added on top of the field sources
+#[derive(Debug, Default, Component)]
+pub struct PaintPlain;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum EditMode {
+    #[default]
+    Idle,
+    /// Left button down on empty space, becomes painting once dragged or
+    /// spawns a ball when released in place
+    Pressed(Vec2),
+    Painting,
+    Erasing,
+    Dragging(Entity),
+}
+
+/// Mouse state of the sandbox
+#[derive(Debug)]
+pub struct MouseEdit {
+    pub brush: Brush,
+    /// Distance the cursor has to move before a press turns into painting
+    pub drag_distance: f32,
+    mode: EditMode,
+    last: Option<Vec2>,
+}
+
+impl Default for MouseEdit {
+    fn default() -> Self {
+        Self {
+            brush: Brush::new(4.0, 0.05).with_falloff(Falloff::Smooth),
+            drag_distance: 1.0,
+            mode: EditMode::default(),
+            last: None,
+        }
+    }
+}
+
+/// Where the cursor ray hits the z = 0 plane the plain lies in
+pub fn cursor_on_plain(
+    windows: &Windows,
+    camera: &Camera,
+    transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let size = Vec2::new(window.width(), window.height());
+    let ndc = cursor / size * 2.0 - Vec2::ONE;
+
+    // depth 1 is the near plane, anything smaller lies further along the ray
+    let ndc_to_world = transform.compute_matrix() * camera.projection_matrix.inverse();
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(0.01));
+    let direction = far - near;
+    if direction.z.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = -near.z / direction.z;
+    if t < 0.0 {
+        return None;
+    }
+    Some((near + direction * t).truncate())
+}
+
+pub fn mouse_editing(
+    mut commands: Commands,
+    mut edit: ResMut<MouseEdit>,
+    (windows, buttons, time): (Res<Windows>, Res<Input<MouseButton>>, Res<Time>),
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut paint: Query<&mut ValuePlain, With<PaintPlain>>,
+    mut balls: Query<(Entity, &mut Position, &Radius, &mut Veclocity), With<Ball>>,
+    mut edits: EventWriter<PlainEdited>,
+) {
+    let point = cameras
+        .iter()
+        .find_map(|(camera, transform)| cursor_on_plain(&windows, camera, transform));
+    let point = match point {
+        Some(p) => p,
+        None => {
+            edit.last = None;
+            return;
+        }
+    };
+    let last = edit.last.unwrap_or(point);
+    edit.last = Some(point);
+
+    if buttons.just_pressed(MouseButton::Left) {
+        let grabbed = balls
+            .iter()
+            .filter(|(_, p, r, _)| p.pos.distance(point) < r.r.max(2.0))
+            .min_by(|a, b| a.1.pos.distance(point).total_cmp(&b.1.pos.distance(point)))
+            .map(|(e, ..)| e);
+        edit.mode = match grabbed {
+            Some(e) => EditMode::Dragging(e),
+            None => EditMode::Pressed(point),
+        };
+    } else if buttons.just_pressed(MouseButton::Right) {
+        edit.mode = EditMode::Erasing;
+    }
+
+    let mut from = last;
+    if let EditMode::Pressed(start) = edit.mode {
+        if start.distance(point) > edit.drag_distance {
+            edit.mode = EditMode::Painting;
+            // the stroke starts where the button went down
+            from = start;
+        }
+    }
+
+    let op = match edit.mode {
+        EditMode::Painting => Some(BrushOp::Add),
+        EditMode::Erasing => Some(BrushOp::Subtract),
+        _ => None,
+    };
+    if let (Some(op), Some(mut plain)) = (op, paint.iter_mut().next()) {
+        if let Some(rect) = edit.brush.stroke(&mut plain, from, point, op) {
+            edits.send(PlainEdited(rect));
+        }
+    }
+
+    if let EditMode::Dragging(entity) = edit.mode {
+        if let Ok((_, mut pos, _, mut vel)) = balls.get_mut(entity) {
+            pos.pos = point;
+            // keeps moving with the last drag motion once released
+            vel.vel = (point - last) / time.delta_seconds().max(f32::EPSILON);
+        }
+    }
+
+    let released = match edit.mode {
+        EditMode::Erasing => buttons.just_released(MouseButton::Right),
+        EditMode::Idle => false,
+        _ => buttons.just_released(MouseButton::Left),
+    };
+    if released {
+        if let EditMode::Pressed(start) = edit.mode {
+            let mut rng = rand::thread_rng();
+            commands
+                .spawn()
+                .insert(Ball)
+                .insert(Position { pos: start })
+                .insert(Radius {
+                    r: rng.gen_range(1.0..4.0),
+                })
+                .insert(Veclocity {
+                    vel: Vec2::new(rng.gen_range(-30.0..30.0), rng.gen_range(-30.0..30.0)),
+                });
+        }
+        edit.mode = EditMode::Idle;
+    }
+}
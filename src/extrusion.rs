@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+use std::collections::HashSet;
+
+use crate::marching_squares::MarchingSquares;
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+use crate::threshold_layer::ThresholdLayer;
+use crate::value_plain::ValuePlain;
+
+type EdgeKey = ((i64, i64), (i64, i64));
+
+/// Turns a layer region into a closed solid. The top cap stays at z = 0
+/// and the bottom cap goes down to z = -depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extrusion {
+    pub depth: f32,
+}
+
+impl Default for Extrusion {
+    fn default() -> Self {
+        Self { depth: 5.0 }
+    }
+}
+
+impl Extrusion {
+    pub fn new(depth: f32) -> Self {
+        Self { depth }
+    }
+
+    pub fn mesh_from_plain(&self, plain: &ValuePlain, layer: &ThresholdLayer) -> Mesh {
+        let (vertices, values, indices) = MarchingSquares::default()
+            .split_squares(true)
+            .triangles(plain, layer);
+        self.mesh_from_triangles(
+            &vertices,
+            &values,
+            &indices,
+            &layer.settings,
+            plain.bounds(),
+        )
+    }
+
+    /// `values` holds the field value of each vertex, every vertex of the
+    /// solid takes the value of the cap vertex above or below it, so a
+    /// `color_ramp` colors the walls like the top
+    pub fn mesh_from_triangles(
+        &self,
+        vertices: &[[f32; 3]],
+        values: &[f32],
+        indices: &[u32],
+        settings: &MeshSettings,
+        bounds: (Vec2, Vec2),
+    ) -> Mesh {
+        let mut out_vertices = Vec::new();
+        let mut out_values = Vec::new();
+        let mut out_indices = Vec::new();
+
+        let mut edges = Vec::new();
+        let mut edge_set: HashSet<EdgeKey> = HashSet::new();
+
+        for t in indices.chunks_exact(3) {
+            let mut t = [t[0] as usize, t[1] as usize, t[2] as usize];
+            // caps and walls rely on every triangle facing +Z
+            let p = t.map(|i| Vec3::from(vertices[i]));
+            if (p[1] - p[0]).truncate().perp_dot((p[2] - p[0]).truncate()) < 0.0 {
+                t.swap(1, 2);
+            }
+            let p = t.map(|i| Vec3::from(vertices[i]));
+            let v = t.map(|i| values[i]);
+
+            let top = p.map(|p| Vec3::new(p.x, p.y, 0.0));
+            let bottom = p.map(|p| Vec3::new(p.x, p.y, -self.depth));
+            Self::push_polygon(&mut out_vertices, &mut out_indices, &top);
+            Self::push_polygon(
+                &mut out_vertices,
+                &mut out_indices,
+                &[bottom[0], bottom[2], bottom[1]],
+            );
+            out_values.extend(v);
+            out_values.extend([v[0], v[2], v[1]]);
+
+            for k in 0..3 {
+                let a = (p[k], v[k]);
+                let b = (p[(k + 1) % 3], v[(k + 1) % 3]);
+                if edge_set.insert((Self::key(a.0), Self::key(b.0))) {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        // an edge whose reverse is not used by a neighbour lies on the contour
+        for ((a, a_value), (b, b_value)) in edges {
+            if edge_set.contains(&(Self::key(b), Self::key(a))) || Self::key(a) == Self::key(b) {
+                continue;
+            }
+            let a_top = Vec3::new(a.x, a.y, 0.0);
+            let b_top = Vec3::new(b.x, b.y, 0.0);
+            let a_bottom = Vec3::new(a.x, a.y, -self.depth);
+            let b_bottom = Vec3::new(b.x, b.y, -self.depth);
+            Self::push_polygon(
+                &mut out_vertices,
+                &mut out_indices,
+                &[a_bottom, b_bottom, b_top, a_top],
+            );
+            out_values.extend([a_value, b_value, b_value, a_value]);
+        }
+
+        let mut mesh = build_mesh(out_vertices, out_indices, None, settings, bounds);
+        if let Some(ramp) = &settings.color_ramp {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, ramp.vertex_colors(&out_values));
+        }
+        mesh
+    }
+
+    fn key(v: Vec3) -> (i64, i64) {
+        ((v.x * 1024.0).round() as i64, (v.y * 1024.0).round() as i64)
+    }
+
+    /// Pushes a convex polygon as a fan with its own vertices, so normals
+    /// are not shared with neighbouring faces
+    fn push_polygon(vertices: &mut Vec<[f32; 3]>, indices: &mut Vec<u32>, polygon: &[Vec3]) {
+        let first = vertices.len() as u32;
+        vertices.extend(polygon.iter().map(|v| -> [f32; 3] { (*v).into() }));
+        for i in 1..(polygon.len() as u32 - 1) {
+            indices.extend([first, first + i, first + i + 1]);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use bevy::render::{
+    mesh::{Indices, Mesh},
+    render_resource::PrimitiveTopology,
+};
+
+use crate::contour;
+use crate::mesh_attributes::{build_mesh, compute_uvs, MeshSettings};
+use crate::value_plain::ValuePlain;
+
+/// Contour lines drawn every `interval` of the field value. They are written
+/// into the vertex colors, so the heightmap needs a `VertexColorMaterial`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContourOverlay {
+    pub interval: f32,
+    /// How close to a level a vertex has to be to get the line color
+    pub width: f32,
+    pub color: Color,
+}
+
+/// Displaces the plain along Z by its values, z = value * scale
+#[derive(Debug, Clone, Component)]
+pub struct Heightmap {
+    pub scale: f32,
+    /// Values are clamped to this before scaling, metaballs go to infinity
+    /// at their centers
+    pub max_value: f32,
+    pub settings: MeshSettings,
+    pub overlay: Option<ContourOverlay>,
+}
+
+impl Default for Heightmap {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            max_value: f32::MAX,
+            settings: MeshSettings::default(),
+            overlay: None,
+        }
+    }
+}
+
+impl Heightmap {
+    pub fn new(scale: f32, max_value: f32) -> Self {
+        Self {
+            scale,
+            max_value,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_settings(mut self, settings: MeshSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_overlay(mut self, overlay: ContourOverlay) -> Self {
+        self.overlay = Some(overlay);
+        self
+    }
+
+    fn height(&self, value: f32) -> f32 {
+        value.min(self.max_value) * self.scale
+    }
+
+    pub fn mesh_from_plain(&self, plain: &ValuePlain) -> Mesh {
+        let width = plain.width as usize;
+        let height = plain.height as usize;
+
+        let heights = plain
+            .values
+            .iter()
+            .map(|v| self.height(*v))
+            .collect::<Vec<_>>();
+        let vertices = plain
+            .positions
+            .iter()
+            .zip(heights.iter())
+            .map(|(p, h)| [p.x, p.y, *h])
+            .collect::<Vec<_>>();
+
+        let mut indices = Vec::with_capacity((width - 1) * (height - 1) * 6);
+        for j in 0..(plain.height - 1) {
+            for i in 0..(plain.width - 1) {
+                let [a, d, c, b] = contour::cell_corners(plain, i, j).map(|p| p as u32);
+                indices.extend([a, d, c, a, c, b]);
+            }
+        }
+
+        // central differences, rows go down in world Y
+        let mut normals = Vec::with_capacity(vertices.len());
+        for j in 0..height {
+            for i in 0..width {
+                let left = j * width + i.saturating_sub(1);
+                let right = j * width + (i + 1).min(width - 1);
+                let up = j.saturating_sub(1) * width + i;
+                let down = (j + 1).min(height - 1) * width + i;
+
+                let dx = plain.positions[right].x - plain.positions[left].x;
+                let dy = plain.positions[up].y - plain.positions[down].y;
+                let dh_dx = if dx != 0.0 {
+                    (heights[right] - heights[left]) / dx
+                } else {
+                    0.0
+                };
+                let dh_dy = if dy != 0.0 {
+                    (heights[up] - heights[down]) / dy
+                } else {
+                    0.0
+                };
+                normals.push(Vec3::new(-dh_dx, -dh_dy, 1.0).normalize().into());
+            }
+        }
+
+        let mut mesh = build_mesh(
+            vertices,
+            indices,
+            Some(normals),
+            &self.settings,
+            plain.bounds(),
+        );
+        if let Some(colors) = self.vertex_colors(plain) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        }
+        mesh
+    }
+
+    fn vertex_colors(&self, plain: &ValuePlain) -> Option<Vec<u32>> {
+        if self.overlay.is_none() && self.settings.color_ramp.is_none() {
+            return None;
+        }
+        let colors = plain
+            .values
+            .iter()
+            .map(|v| {
+                if let Some(overlay) = &self.overlay {
+                    let level = (v / overlay.interval).round() * overlay.interval;
+                    if level > 0.0 && (v - level).abs() < overlay.width {
+                        return overlay.color.as_linear_rgba_u32();
+                    }
+                }
+                match &self.settings.color_ramp {
+                    Some(ramp) => ramp.sample(*v).as_linear_rgba_u32(),
+                    None => Color::WHITE.as_linear_rgba_u32(),
+                }
+            })
+            .collect();
+        Some(colors)
+    }
+
+    /// Contour lines of the overlay as a separate line list mesh, every
+    /// line sits at the height of its level
+    pub fn contour_lines(&self, plain: &ValuePlain) -> Mesh {
+        let mut vertices = Vec::new();
+        if let Some(overlay) = &self.overlay {
+            let max = plain
+                .values
+                .iter()
+                .fold(0.0_f32, |max, v| max.max(*v))
+                .min(self.max_value);
+            let levels = ((max / overlay.interval) as u32).min(256);
+            for l in 1..=levels {
+                let level = l as f32 * overlay.interval;
+                let z = self.height(level);
+                for (a, b) in contour::segments(plain, level) {
+                    vertices.push([a.x, a.y, z]);
+                    vertices.push([b.x, b.y, z]);
+                }
+            }
+        }
+        let indices = (0..vertices.len() as u32).collect::<Vec<_>>();
+
+        let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_NORMAL,
+            vec![[0.0, 0.0, 1.0]; vertices.len()],
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_UV_0,
+            compute_uvs(&vertices, self.settings.uv_mode, plain.bounds()),
+        );
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    pub fn update_mesh(
+        &self,
+        plain: &ValuePlain,
+        mesh_handle: Handle<Mesh>,
+        meshes: &mut ResMut<Assets<Mesh>>,
+    ) {
+        let mesh = self.mesh_from_plain(plain);
+        if let Some(m) = meshes.get_mut(&mesh_handle) {
+            *m = mesh;
+        }
+    }
+}
+
+/// Marks the line mesh showing the contours of a `Heightmap` entity
+#[derive(Debug, Default, Component)]
+pub struct HeightmapContours;
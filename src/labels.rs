@@ -0,0 +1,191 @@
+use crate::contour;
+use crate::threshold_layer::ThresholdLayer;
+use crate::value_plain::ValuePlain;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only samples sharing an edge are connected
+    Four,
+    /// Diagonal samples are connected as well
+    Eight,
+    /// Diagonal samples are connected when the middle of their cell is
+    /// inside, same saddle rule as the contours
+    #[default]
+    Saddle,
+}
+
+/// Region id of every inside sample of a layer
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    pub labels: Vec<Option<u32>>,
+    pub count: u32,
+}
+
+impl Labels {
+    pub fn from_layer(
+        plain: &ValuePlain,
+        layer: &ThresholdLayer,
+        connectivity: Connectivity,
+    ) -> Self {
+        let (width, height) = (plain.width, plain.height);
+        let inside = &layer.normalized_values;
+        let mut parents = (0..inside.len()).collect::<Vec<_>>();
+
+        for j in 0..height {
+            for i in 0..width {
+                let a = plain.index(i, j);
+                if !inside[a] {
+                    continue;
+                }
+                if i + 1 < width && inside[a + 1] {
+                    union(&mut parents, a, a + 1);
+                }
+                if j + 1 == height {
+                    continue;
+                }
+                let d = plain.index(i, j + 1);
+                if inside[d] {
+                    union(&mut parents, a, d);
+                }
+                // diagonals of the cell right below and left below
+                for (other, cell) in [(i + 1, i), (i.wrapping_sub(1), i.wrapping_sub(1))] {
+                    if other >= width {
+                        continue;
+                    }
+                    let o = plain.index(other, j + 1);
+                    if inside[o] && connected(plain, layer, connectivity, cell, j) {
+                        union(&mut parents, a, o);
+                    }
+                }
+            }
+        }
+
+        let mut ids = vec![None; inside.len()];
+        let mut count = 0;
+        let mut labels = vec![None; inside.len()];
+        for p in 0..inside.len() {
+            if !inside[p] {
+                continue;
+            }
+            let root = find(&mut parents, p);
+            let id = *ids[root].get_or_insert_with(|| {
+                count += 1;
+                count - 1
+            });
+            labels[p] = Some(id);
+        }
+        Self { labels, count }
+    }
+
+    /// Copy of the plain where inside samples of every other label are
+    /// mirrored below the threshold, so only `label` is left inside
+    pub fn mask(&self, plain: &ValuePlain, threshold: f32, label: u32) -> ValuePlain {
+        let mut masked = plain.clone();
+        for (v, l) in masked.values.iter_mut().zip(self.labels.iter()) {
+            if matches!(l, Some(l) if *l != label) {
+                *v = threshold - (*v - threshold);
+            }
+        }
+        masked
+    }
+}
+
+/// Whether the two diagonals of cell (i, j) connect their inside corners
+fn connected(
+    plain: &ValuePlain,
+    layer: &ThresholdLayer,
+    connectivity: Connectivity,
+    i: u32,
+    j: u32,
+) -> bool {
+    match connectivity {
+        Connectivity::Four => false,
+        Connectivity::Eight => true,
+        Connectivity::Saddle => {
+            let corners = contour::cell_corners(plain, i, j);
+            let center = corners.iter().map(|c| plain.values[*c]).sum::<f32>() * 0.25;
+            center > layer.threshold
+        }
+    }
+}
+
+fn find(parents: &mut [usize], mut p: usize) -> usize {
+    while parents[p] != p {
+        parents[p] = parents[parents[p]];
+        p = parents[p];
+    }
+    p
+}
+
+fn union(parents: &mut [usize], a: usize, b: usize) {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    if a != b {
+        parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Layer inside exactly at the given samples, the field is 1 there and
+    /// 0 elsewhere, the threshold 0.5
+    fn layer(rows: &[&str]) -> (ValuePlain, ThresholdLayer) {
+        let (width, height) = (rows[0].len() as u32, rows.len() as u32);
+        let mut plain = ValuePlain::new(width, height);
+        for (j, row) in rows.iter().enumerate() {
+            for (i, c) in row.chars().enumerate() {
+                let index = plain.index(i as u32, j as u32);
+                plain.values[index] = if c == '#' { 1.0 } else { 0.0 };
+            }
+        }
+        let mut layer = ThresholdLayer::new(width, height, 0.5);
+        layer.update_values(&plain);
+        (plain, layer)
+    }
+
+    #[test]
+    fn diagonals_depend_on_the_connectivity() {
+        let (plain, layer) = layer(&["#...", ".#..", "...#", "..#."]);
+        let count = |c| Labels::from_layer(&plain, &layer, c).count;
+        assert_eq!(count(Connectivity::Four), 4);
+        assert_eq!(count(Connectivity::Eight), 2);
+        // the middle of every cell is at 0.5, not inside
+        assert_eq!(count(Connectivity::Saddle), 4);
+    }
+
+    #[test]
+    fn saddle_connects_when_the_cell_middle_is_inside() {
+        let (mut plain, mut layer) = layer(&["##..", "##..", "..##", "..##"]);
+        let count = |plain: &ValuePlain, layer: &ThresholdLayer| {
+            Labels::from_layer(plain, layer, Connectivity::Saddle).count
+        };
+        assert_eq!(count(&plain, &layer), 2);
+
+        // the outside corners of the touching cell rise, but stay outside
+        for (i, j) in [(2, 1), (1, 2)] {
+            let index = plain.index(i, j);
+            plain.values[index] = 0.4;
+        }
+        layer.update_values(&plain);
+        assert_eq!(count(&plain, &layer), 1);
+        assert_eq!(
+            Labels::from_layer(&plain, &layer, Connectivity::Four).count,
+            2
+        );
+    }
+
+    #[test]
+    fn mask_keeps_one_label_inside() {
+        let (plain, layer) = layer(&["##..", "##..", "...#", "..##"]);
+        let labels = Labels::from_layer(&plain, &layer, Connectivity::Four);
+        assert_eq!(labels.count, 2);
+        for label in 0..labels.count {
+            let masked = labels.mask(&plain, 0.5, label);
+            for (v, l) in masked.values.iter().zip(labels.labels.iter()) {
+                assert_eq!(*v > 0.5, *l == Some(label));
+            }
+        }
+    }
+}
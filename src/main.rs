@@ -2,25 +2,79 @@ use bevy::prelude::*;
 use bevy::render::render_resource::PrimitiveTopology;
 
 mod ball;
+mod brush;
+mod cave;
+mod color_ramp;
+mod contour;
+mod csg;
+mod dual_contouring;
+mod editor;
+mod extrusion;
+mod heightmap;
+mod labels;
+mod marching_cubes;
 mod marching_squares;
+mod marching_triangles;
+mod mesh_attributes;
+mod noise;
+mod perlin;
+mod polygon;
+mod primitive;
+mod quadtree_plain;
+mod raycast;
+mod region;
+mod sdf;
+mod simplify;
+mod smooth;
 mod threshold_layer;
 mod value_plain;
+mod value_volume;
+mod vertex_color;
 
-use crate::ball::{Ball, Position, Radius, Veclocity};
+use crate::ball::{Ball, Kernel, Metaball, Position, Radius, Veclocity, Weight};
+use crate::brush::PlainEdited;
+use crate::cave::CaveGenerator;
+use crate::color_ramp::ColorRamp;
+use crate::csg::Csg;
+use crate::editor::{MouseEdit, PaintPlain};
+use crate::extrusion::Extrusion;
+use crate::heightmap::{ContourOverlay, Heightmap, HeightmapContours};
+use crate::labels::Connectivity;
+use crate::marching_cubes::MarchingCubes;
+use crate::mesh_attributes::MeshSettings;
+use crate::noise::NoiseField;
+use crate::perlin::PerlinField;
+use crate::primitive::{Primitive, Shape};
+use crate::quadtree_plain::QuadtreePlain;
+use crate::region::Regions;
 use crate::threshold_layer::ThresholdLayer;
 use crate::value_plain::ValuePlain;
+use crate::value_volume::ValueVolume;
+use crate::vertex_color::{VertexColorMaterial, VertexColorPlugin};
 
 fn main() {
     App::new()
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(ClearColor(Color::rgb_u8(69, 69, 69)))
         .add_plugins(DefaultPlugins)
+        .add_plugin(VertexColorPlugin)
+        .init_resource::<FieldSource>()
+        .init_resource::<MouseEdit>()
+        .add_event::<PlainEdited>()
         .add_startup_system(setup)
         .add_startup_system(ball::setup)
         .add_startup_system(setup_plain_and_layers)
         .add_system(update_balls)
+        .add_system(switch_field_source)
+        .add_system(editor::mouse_editing.before(update_plain))
         .add_system(update_plain)
-        .add_system(update_layers)
+        .add_system(update_layers.after(update_plain))
+        .add_system(update_regions.after(update_plain))
+        .add_system(print_regions)
+        .add_system(update_heightmaps.after(update_plain))
+        .add_system(update_volumes)
+        .add_system(update_quadtrees)
+        .add_system(update_region_meshes.after(update_plain))
         .add_system(camera_movement)
         .run();
 }
@@ -41,12 +95,13 @@ fn setup(mut commands: Commands) {
 pub fn update_balls(
     plain: Query<&ValuePlain, With<MetaballsPlain>>,
     mut q: Query<(&mut Position, &mut Veclocity), With<Ball>>,
+    time: Res<Time>,
 ) {
     if let Some(plain) = plain.iter().next() {
         let half_width = plain.width as f32 * 0.5;
         let half_height = plain.height as f32 * 0.5;
         for (mut pos, mut vel) in q.iter_mut() {
-            pos.pos += vel.vel;
+            pos.pos += vel.vel * time.delta_seconds();
             if pos.pos.x > half_width || pos.pos.x < -half_width {
                 vel.vel.x *= -1.0;
             }
@@ -60,10 +115,46 @@ pub fn update_balls(
 #[derive(Debug, Default, Component)]
 pub struct MetaballsPlain;
 
+/// What drives the plain, switched with the number keys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldSource {
+    #[default]
+    Metaballs,
+    Noise,
+    /// Sum of both
+    Blend,
+    /// Domain warped simplex noise, animated along its z coordinate
+    Simplex,
+    /// Cellular automaton cave, generated once
+    Cave,
+}
+
+impl FieldSource {
+    /// Whether the field changes every frame, without any edits
+    pub fn is_animated(&self) -> bool {
+        *self != FieldSource::Cave
+    }
+}
+
+/// Static field generated at startup and added to the plain by
+/// `FieldSource::Cave`
+#[derive(Debug, Default, Component)]
+pub struct CavePlain;
+
+/// Meshes every region of the layer on the same entity as its own child,
+/// children are reused between frames
+#[derive(Debug, Default, Component)]
+pub struct RegionMeshes {
+    pub connectivity: Connectivity,
+    /// Materials handed out to the regions in label order
+    pub palette: Vec<Handle<StandardMaterial>>,
+}
+
 pub fn setup_plain_and_layers(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut standart_materials: ResMut<Assets<StandardMaterial>>,
+    mut vertex_color_materials: ResMut<Assets<VertexColorMaterial>>,
 ) {
     let width = 100;
     let height = 100;
@@ -72,6 +163,17 @@ pub fn setup_plain_and_layers(
         .spawn()
         .insert(ValuePlain::new(width, height))
         .insert(MetaballsPlain);
+    commands
+        .spawn()
+        .insert(ValuePlain::new(width, height))
+        .insert(PaintPlain);
+    let mut cave = ValuePlain::new(width, height);
+    CaveGenerator::default().with_seed(7).generate(&mut cave);
+    // walls near 0.25 sit above every layer threshold
+    for v in cave.values.iter_mut() {
+        *v *= 0.25;
+    }
+    commands.spawn().insert(cave).insert(CavePlain);
 
     let thresholds = [0.2, 0.1, 0.05, 0.04, 0.03];
     let colors = [
@@ -82,12 +184,7 @@ pub fn setup_plain_and_layers(
         Color::rgb_u8(42, 50, 88),
     ];
 
-    for (i, (t, c)) in thresholds
-        .into_iter()
-        .zip(colors.into_iter())
-        .rev()
-        .enumerate()
-    {
+    for (i, (t, c)) in thresholds.into_iter().zip(colors).rev().enumerate() {
         commands
             .spawn_bundle(PbrBundle {
                 material: standart_materials.add(c.into()),
@@ -95,20 +192,233 @@ pub fn setup_plain_and_layers(
                 transform: Transform::from_translation(Vec3::new(0.0, 0.0, 5.0 * i as f32)),
                 ..Default::default()
             })
-            .insert(ThresholdLayer::new(width, height, t));
+            .insert(ThresholdLayer::new(width, height, t))
+            .insert(Regions::default());
     }
+
+    // the same thresholds as one heat map mesh
+    let ramp = ColorRamp::new(thresholds.into_iter().zip(colors).collect());
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            material: vertex_color_materials.add(VertexColorMaterial),
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            transform: Transform::from_translation(Vec3::new(0.0, -120.0, 0.0)),
+            ..Default::default()
+        })
+        .insert(
+            ThresholdLayer::new(width, height, 0.03).with_settings(MeshSettings {
+                color_ramp: Some(ramp.clone()),
+                ..Default::default()
+            }),
+        );
+
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            material: vertex_color_materials.add(VertexColorMaterial),
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            transform: Transform::from_translation(Vec3::new(120.0, 0.0, 0.0)),
+            ..Default::default()
+        })
+        .insert(
+            ThresholdLayer::new(width, height, 0.05)
+                .with_settings(MeshSettings {
+                    color_ramp: Some(ramp.clone()),
+                    ..Default::default()
+                })
+                .with_extrusion(Extrusion::new(10.0)),
+        );
+
+    commands
+        .spawn()
+        .insert(PerlinField::default().with_seed(7).with_amplitude(0.3));
+    commands.spawn().insert(
+        NoiseField::default()
+            .with_seed(7)
+            .with_warp(10.0)
+            .with_range(0.15, 0.05),
+    );
+
+    commands.spawn().insert(
+        Primitive::new(Shape::Star {
+            radius: 10.0,
+            inner_radius: 5.0,
+            points: 5,
+        })
+        .with_translation(Vec2::new(-30.0, -30.0))
+        .with_rotation(0.3),
+    );
+    commands.spawn().insert(
+        Primitive::new(Shape::Ring {
+            radius: 8.0,
+            thickness: 3.0,
+        })
+        .with_translation(Vec2::new(30.0, -30.0)),
+    );
+
+    // half the plain resolution, scaled back up by the transform
+    commands
+        .spawn_bundle(PbrBundle {
+            material: standart_materials.add(Color::rgb_u8(198, 95, 194).into()),
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            transform: Transform::from_translation(Vec3::new(0.0, 120.0, 0.0))
+                .with_scale(Vec3::splat(2.0)),
+            ..Default::default()
+        })
+        .insert(ValueVolume::new(width / 2, height / 2, 16))
+        .insert(MarchingCubes::new(1.0));
+
+    // samples the balls directly, only where the contour or a steep field
+    // needs it
+    commands
+        .spawn_bundle(PbrBundle {
+            material: standart_materials.add(Color::rgb_u8(250, 110, 229).into()),
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            transform: Transform::from_translation(Vec3::new(-120.0, 120.0, 0.0)),
+            ..Default::default()
+        })
+        .insert(
+            QuadtreePlain::new(64, width as f32 / 64.0, 0.05)
+                .with_min_depth(3)
+                .with_gradient_limit(0.02),
+        );
+
+    let palette = [
+        Color::rgb_u8(230, 97, 92),
+        Color::rgb_u8(240, 200, 80),
+        Color::rgb_u8(95, 185, 120),
+        Color::rgb_u8(80, 150, 220),
+    ];
+    commands
+        .spawn_bundle(TransformBundle::from_transform(
+            Transform::from_translation(Vec3::new(120.0, 120.0, 0.0)),
+        ))
+        .insert(ThresholdLayer::new(width, height, 0.05))
+        .insert(RegionMeshes {
+            connectivity: Connectivity::default(),
+            palette: palette
+                .into_iter()
+                .map(|c| standart_materials.add(c.into()))
+                .collect(),
+        });
+
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            material: vertex_color_materials.add(VertexColorMaterial),
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::TriangleList)),
+            transform: Transform::from_translation(Vec3::new(-120.0, 0.0, 0.0)),
+            ..Default::default()
+        })
+        .insert(
+            Heightmap::new(50.0, 0.4)
+                .with_settings(MeshSettings {
+                    color_ramp: Some(ramp),
+                    ..Default::default()
+                })
+                .with_overlay(ContourOverlay {
+                    interval: 0.05,
+                    width: 0.005,
+                    color: Color::WHITE,
+                }),
+        )
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(PbrBundle {
+                    material: standart_materials.add(StandardMaterial {
+                        base_color: Color::WHITE,
+                        unlit: true,
+                        ..Default::default()
+                    }),
+                    mesh: meshes.add(Mesh::new(PrimitiveTopology::LineList)),
+                    ..Default::default()
+                })
+                .insert(HeightmapContours);
+        });
 }
 
+/// Kernel and weight of a ball, the inverse square at full weight unless
+/// it has its own
+fn falloff(kernel: Option<&Kernel>, weight: Option<&Weight>) -> (Kernel, f32) {
+    (
+        kernel.copied().unwrap_or_default(),
+        weight.map_or(1.0, |w| w.w),
+    )
+}
+
+type BallComponents<'a> = (
+    &'a Position,
+    &'a Radius,
+    Option<&'a Kernel>,
+    Option<&'a Weight>,
+);
+
+/// Plain added onto the metaballs plain, picked by its marker component
+type SourcePlain<'w, 's, M> =
+    Query<'w, 's, &'static ValuePlain, (With<M>, Without<MetaballsPlain>)>;
+
+/// Recomputes the plain when the source is animated, switched or edited.
+/// Otherwise the plain is left untouched, so the systems filtering on
+/// `Changed<ValuePlain>` skip it too.
 pub fn update_plain(
     mut plain: Query<&mut ValuePlain, With<MetaballsPlain>>,
-    balls: Query<(&Position, &Radius), With<Ball>>,
+    (paint, caves): (SourcePlain<PaintPlain>, SourcePlain<CavePlain>),
+    balls: Query<BallComponents, With<Ball>>,
+    (noises, noise_fields): (Query<&PerlinField>, Query<&NoiseField>),
+    primitives: Query<&Primitive>,
+    (source, time): (Res<FieldSource>, Res<Time>),
+    mut edits: EventReader<PlainEdited>,
 ) {
+    let edited = edits.iter().count() > 0;
+    if !(source.is_animated() || source.is_changed() || edited) {
+        return;
+    }
     if let Some(mut plain) = plain.iter_mut().next() {
-        plain.update(&|x, y| {
-            balls
-                .iter()
-                .fold(0.0, |sum, (p, r)| sum + Ball::calc(&p.pos, r.r, x, y))
-        });
+        plain.values.fill(0.0);
+        if matches!(*source, FieldSource::Metaballs | FieldSource::Blend) {
+            for (p, r, kernel, weight) in balls.iter() {
+                let (kernel, weight) = falloff(kernel, weight);
+                plain.splat(&Metaball {
+                    pos: p.pos,
+                    r: r.r,
+                    kernel,
+                    weight,
+                });
+            }
+        }
+        if let Some(paint) = paint.iter().next() {
+            for (v, p) in plain.values.iter_mut().zip(paint.values.iter()) {
+                *v += p;
+            }
+        }
+        if *source == FieldSource::Cave {
+            for cave in caves.iter() {
+                for (v, c) in plain.values.iter_mut().zip(cave.values.iter()) {
+                    *v += c;
+                }
+            }
+        }
+        let time = time.seconds_since_startup() as f32;
+        let ValuePlain {
+            positions, values, ..
+        } = &mut *plain;
+        for (v, pos) in values.iter_mut().zip(positions.iter()) {
+            if matches!(*source, FieldSource::Noise | FieldSource::Blend) {
+                *v += noises
+                    .iter()
+                    .map(|n| n.value(pos.x, pos.y, time))
+                    .sum::<f32>();
+            }
+            if *source == FieldSource::Simplex {
+                // z moves at five world units per second
+                *v += noise_fields
+                    .iter()
+                    .map(|n| n.value(pos.x, pos.y, time * 5.0))
+                    .sum::<f32>();
+            }
+            // shapes are positive inside, so a union puts them on every layer
+            *v = primitives.iter().fold(*v, |value, p| {
+                Csg::Union.apply(value, p.field(pos.x, pos.y))
+            });
+        }
     } else {
         println!("no plane");
     }
@@ -116,7 +426,7 @@ pub fn update_plain(
 
 pub fn update_layers(
     mut meshes: ResMut<Assets<Mesh>>,
-    plain: Query<&ValuePlain, With<MetaballsPlain>>,
+    plain: Query<&ValuePlain, (With<MetaballsPlain>, Changed<ValuePlain>)>,
     mut layers: Query<(&mut ThresholdLayer, &Handle<Mesh>)>,
 ) {
     if let Some(plain) = plain.iter().next() {
@@ -126,6 +436,170 @@ pub fn update_layers(
     }
 }
 
+pub fn update_regions(
+    plain: Query<&ValuePlain, (With<MetaballsPlain>, Changed<ValuePlain>)>,
+    mut layers: Query<(&ThresholdLayer, &mut Regions)>,
+) {
+    if let Some(plain) = plain.iter().next() {
+        for (l, mut regions) in layers.iter_mut() {
+            regions.0 = l.regions(plain);
+        }
+    }
+}
+
+pub fn update_region_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    plain: Query<&ValuePlain, (With<MetaballsPlain>, Changed<ValuePlain>)>,
+    mut layers: Query<(
+        Entity,
+        &mut ThresholdLayer,
+        &RegionMeshes,
+        Option<&Children>,
+    )>,
+    region_handles: Query<&Handle<Mesh>>,
+) {
+    if let Some(plain) = plain.iter().next() {
+        for (entity, mut l, region_meshes, children) in layers.iter_mut() {
+            let handles = children
+                .iter()
+                .flat_map(|c| c.iter())
+                .filter_map(|c| region_handles.get(*c).ok())
+                .collect::<Vec<_>>();
+            let mut new_meshes = l
+                .region_meshes(plain, region_meshes.connectivity)
+                .into_iter();
+            // regions that went away keep their child with an empty mesh
+            for h in handles.iter() {
+                if let Some(m) = meshes.get_mut(*h) {
+                    *m = new_meshes
+                        .next()
+                        .unwrap_or_else(|| Mesh::new(PrimitiveTopology::TriangleList));
+                }
+            }
+            for (k, mesh) in (handles.len()..).zip(new_meshes) {
+                let material = region_meshes.palette[k % region_meshes.palette.len()].clone();
+                let mesh = meshes.add(mesh);
+                commands.entity(entity).with_children(|parent| {
+                    parent.spawn_bundle(PbrBundle {
+                        material,
+                        mesh,
+                        ..Default::default()
+                    });
+                });
+            }
+        }
+    }
+}
+
+/// Prints the regions of every layer when R is pressed
+pub fn print_regions(
+    keyboard_input: Res<Input<KeyCode>>,
+    layers: Query<(&ThresholdLayer, &Regions)>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::R) {
+        return;
+    }
+    for (l, regions) in layers.iter() {
+        print!(
+            "threshold {}: {} regions, area {:.1}",
+            l.threshold,
+            regions.0.len(),
+            regions.total_area()
+        );
+        match regions.largest() {
+            Some(r) => println!(
+                ", largest at {} with area {:.1}, perimeter {:.1}, bounds {} to {}, {} holes",
+                r.centroid,
+                r.area,
+                r.perimeter,
+                r.min,
+                r.max,
+                r.hole_count()
+            ),
+            None => println!(),
+        }
+    }
+}
+
+pub fn update_heightmaps(
+    mut meshes: ResMut<Assets<Mesh>>,
+    plain: Query<&ValuePlain, (With<MetaballsPlain>, Changed<ValuePlain>)>,
+    heightmaps: Query<(&Heightmap, &Handle<Mesh>, Option<&Children>)>,
+    contours: Query<&Handle<Mesh>, With<HeightmapContours>>,
+) {
+    if let Some(plain) = plain.iter().next() {
+        for (heightmap, h, children) in heightmaps.iter() {
+            heightmap.update_mesh(plain, h.clone(), &mut meshes);
+            for child in children.iter().flat_map(|c| c.iter()) {
+                if let Ok(h) = contours.get(*child) {
+                    if let Some(m) = meshes.get_mut(h) {
+                        *m = heightmap.contour_lines(plain);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The balls as spheres, the volume is sampled at twice its positions to
+/// match its transform
+pub fn update_volumes(
+    mut meshes: ResMut<Assets<Mesh>>,
+    balls: Query<BallComponents, With<Ball>>,
+    mut volumes: Query<(&mut ValueVolume, &MarchingCubes, &Handle<Mesh>)>,
+) {
+    for (mut volume, marching_cubes, h) in volumes.iter_mut() {
+        volume.update(&|x, y, z| {
+            balls
+                .iter()
+                .map(|(p, r, kernel, weight)| {
+                    let (kernel, weight) = falloff(kernel, weight);
+                    let pos = p.pos.extend(0.0);
+                    Ball::calc_3d(&pos, r.r, &kernel, weight, 2.0 * x, 2.0 * y, 2.0 * z)
+                })
+                .sum()
+        });
+        marching_cubes.update_mesh(&volume, h.clone(), &mut meshes);
+    }
+}
+
+pub fn update_quadtrees(
+    mut meshes: ResMut<Assets<Mesh>>,
+    balls: Query<BallComponents, With<Ball>>,
+    mut quadtrees: Query<(&mut QuadtreePlain, &Handle<Mesh>)>,
+) {
+    for (mut quadtree, h) in quadtrees.iter_mut() {
+        quadtree.update(&|x, y| {
+            balls.iter().fold(0.0, |sum, (p, r, kernel, weight)| {
+                let (kernel, weight) = falloff(kernel, weight);
+                sum + Ball::calc(&p.pos, r.r, &kernel, weight, x, y)
+            })
+        });
+        if let Some(m) = meshes.get_mut(h) {
+            *m = quadtree.mesh(&MeshSettings::default());
+        }
+    }
+}
+
+fn switch_field_source(keyboard_input: Res<Input<KeyCode>>, mut source: ResMut<FieldSource>) {
+    if keyboard_input.just_pressed(KeyCode::Key1) {
+        *source = FieldSource::Metaballs;
+    }
+    if keyboard_input.just_pressed(KeyCode::Key2) {
+        *source = FieldSource::Noise;
+    }
+    if keyboard_input.just_pressed(KeyCode::Key3) {
+        *source = FieldSource::Blend;
+    }
+    if keyboard_input.just_pressed(KeyCode::Key4) {
+        *source = FieldSource::Simplex;
+    }
+    if keyboard_input.just_pressed(KeyCode::Key5) {
+        *source = FieldSource::Cave;
+    }
+}
+
 fn camera_movement(
     keyboard_input: Res<Input<KeyCode>>,
     mut q: Query<&mut Transform, With<bevy::render::camera::Camera>>,
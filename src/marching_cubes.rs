@@ -0,0 +1,291 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+use crate::value_volume::ValueVolume;
+
+/// Cube edge given by its two corners. Corner bits are x, y and z offsets
+/// from the first point of the cube.
+type Edge = (u8, u8);
+
+#[derive(Debug, Component)]
+pub struct MarchingCubes {
+    pub threshold: f32,
+    pub settings: MeshSettings,
+    table: Vec<Vec<[Edge; 3]>>,
+}
+
+impl Default for MarchingCubes {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+impl MarchingCubes {
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            settings: MeshSettings::default(),
+            table: (0..=255).map(Self::case_triangles).collect(),
+        }
+    }
+
+    pub fn with_settings(mut self, settings: MeshSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Builds the triangles of one cube configuration. Every face of the
+    /// cube is contoured like a marching squares cell, face saddles always
+    /// keep the inside corners apart, so neighbouring cubes agree on shared
+    /// faces and the surface stays closed. Face segments are then chained
+    /// into loops and fanned.
+    fn case_triangles(case: u8) -> Vec<[Edge; 3]> {
+        let inside = |c: u8| case & (1 << c) != 0;
+        let edge = |c1: u8, c2: u8| (c1.min(c2), c1.max(c2));
+        // world Y goes down with the row index, same as in ValuePlain
+        let corner_position = |c: u8| {
+            Vec3::new(
+                (c & 1) as f32,
+                -(((c >> 1) & 1) as f32),
+                ((c >> 2) & 1) as f32,
+            )
+        };
+
+        let mut next = BTreeMap::new();
+        for axis in 0..3 {
+            let u = (axis + 1) % 3;
+            let v = (axis + 2) % 3;
+            for side in 0..2u8 {
+                // counter clockwise when looking at the face from outside
+                let mut face = [(0, 0), (1, 0), (1, 1), (0, 1)]
+                    .map(|(cu, cv): (u8, u8)| (side << axis) | (cu << u) | (cv << v));
+                if side == 0 {
+                    face.reverse();
+                }
+
+                let mut crossings = Vec::with_capacity(4);
+                for k in 0..4 {
+                    let c1 = face[k];
+                    let c2 = face[(k + 1) % 4];
+                    if inside(c1) != inside(c2) {
+                        crossings.push((edge(c1, c2), inside(c1)));
+                    }
+                }
+                let n = crossings.len();
+                for k in 0..n {
+                    let (start, leaving) = crossings[k];
+                    if leaving {
+                        next.insert(start, crossings[(k + n - 1) % n].0);
+                    }
+                }
+            }
+        }
+
+        let mut triangles = Vec::new();
+        while let Some((&first, _)) = next.iter().next() {
+            let mut polygon = vec![first];
+            let mut current = next.remove(&first).unwrap();
+            while current != first {
+                polygon.push(current);
+                current = next.remove(&current).unwrap();
+            }
+
+            let midpoint = |e: &Edge| (corner_position(e.0) + corner_position(e.1)) * 0.5;
+            let mut normal = Vec3::ZERO;
+            let mut into_inside = Vec3::ZERO;
+            for (k, e) in polygon.iter().enumerate() {
+                let next = &polygon[(k + 1) % polygon.len()];
+                normal += midpoint(e).cross(midpoint(next));
+                let (c_in, c_out) = if inside(e.0) { (e.0, e.1) } else { (e.1, e.0) };
+                into_inside += corner_position(c_in) - corner_position(c_out);
+            }
+            // normals point away from the inside
+            if normal.dot(into_inside) > 0.0 {
+                polygon.reverse();
+            }
+
+            for k in 1..(polygon.len() - 1) {
+                triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+            }
+        }
+        triangles
+    }
+
+    /// The volume is surrounded by one layer of outside samples, so shapes
+    /// cut by the border get a cap on the border faces and the mesh is
+    /// always closed
+    pub fn mesh_from_volume(&self, volume: &ValueVolume) -> Mesh {
+        let (width, height, depth) = (
+            volume.width as i64,
+            volume.height as i64,
+            volume.depth as i64,
+        );
+        let in_volume = |(i, j, k): (i64, i64, i64)| {
+            (0..width).contains(&i) && (0..height).contains(&j) && (0..depth).contains(&k)
+        };
+        let index = |(i, j, k): (i64, i64, i64)| volume.index(i as u32, j as u32, k as u32);
+        let inside = |p: (i64, i64, i64)| in_volume(p) && volume.values[index(p)] > self.threshold;
+
+        let mut vertex_index = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut normals = Vec::new();
+        let mut indices = Vec::new();
+
+        for k in -1..depth {
+            for j in -1..height {
+                for i in -1..width {
+                    let grid = |c: u8| {
+                        (
+                            i + (c & 1) as i64,
+                            j + ((c >> 1) & 1) as i64,
+                            k + ((c >> 2) & 1) as i64,
+                        )
+                    };
+                    let mut case = 0;
+                    for c in 0..8 {
+                        if inside(grid(c)) {
+                            case |= 1 << c;
+                        }
+                    }
+                    for triangle in &self.table[case as usize] {
+                        for (c1, c2) in triangle {
+                            let g1 = grid(*c1);
+                            let g2 = grid(*c2);
+                            let index = *vertex_index.entry((g1, g2)).or_insert_with(|| {
+                                let (position, normal) = if !in_volume(g1) || !in_volume(g2) {
+                                    // the cap lies on the border sample, facing the padding
+                                    let (g_in, g_out) =
+                                        if in_volume(g1) { (g1, g2) } else { (g2, g1) };
+                                    let outward = Vec3::new(
+                                        (g_out.0 - g_in.0) as f32,
+                                        -(g_out.1 - g_in.1) as f32,
+                                        (g_out.2 - g_in.2) as f32,
+                                    );
+                                    (volume.positions[index(g_in)], outward)
+                                } else {
+                                    let (p1, p2) = (index(g1), index(g2));
+                                    let val_1 = volume.values[p1];
+                                    let val_2 = volume.values[p2];
+                                    let t = ((self.threshold - val_1) / (val_2 - val_1))
+                                        .clamp(0.0, 1.0);
+                                    let gradient = volume
+                                        .gradient(g1.0 as u32, g1.1 as u32, g1.2 as u32)
+                                        .lerp(
+                                            volume.gradient(g2.0 as u32, g2.1 as u32, g2.2 as u32),
+                                            t,
+                                        );
+                                    (
+                                        volume.positions[p1].lerp(volume.positions[p2], t),
+                                        -gradient.normalize_or_zero(),
+                                    )
+                                };
+                                vertices.push(position.into());
+                                normals.push(if normal == Vec3::ZERO {
+                                    [0.0, 0.0, 1.0]
+                                } else {
+                                    normal.into()
+                                });
+                                vertices.len() as u32 - 1
+                            });
+                            indices.push(index);
+                        }
+                    }
+                }
+            }
+        }
+
+        let (min, max) = volume.bounds();
+        build_mesh(
+            vertices,
+            indices,
+            Some(normals),
+            &self.settings,
+            (min.truncate(), max.truncate()),
+        )
+    }
+
+    pub fn update_mesh(
+        &self,
+        volume: &ValueVolume,
+        mesh_handle: Handle<Mesh>,
+        meshes: &mut ResMut<Assets<Mesh>>,
+    ) {
+        let mesh = self.mesh_from_volume(volume);
+        if let Some(m) = meshes.get_mut(&mesh_handle) {
+            *m = mesh;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ball::{Ball, Kernel};
+    use bevy::render::mesh::{Indices, VertexAttributeValues};
+
+    /// Counts how often every undirected edge is used by a triangle
+    fn edge_uses(mesh: &Mesh) -> HashMap<(u32, u32), u32> {
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices,
+            _ => panic!("expected u32 indices"),
+        };
+        let mut uses = HashMap::new();
+        for t in indices.chunks_exact(3) {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                *uses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        uses
+    }
+
+    fn assert_closed(volume: &ValueVolume) {
+        let mesh = MarchingCubes::new(1.0).mesh_from_volume(volume);
+        let uses = edge_uses(&mesh);
+        assert!(!uses.is_empty());
+        for (edge, count) in uses {
+            assert_eq!(count, 2, "edge {:?} is used by {} triangles", edge, count);
+        }
+    }
+
+    #[test]
+    fn sphere_cut_by_the_border_is_closed() {
+        let mut volume = ValueVolume::new(12, 10, 8);
+        volume.update(&|x, y, z| 16.0 / ((x - 4.0).powi(2) + (y + 1.0).powi(2) + z * z));
+        assert_closed(&volume);
+    }
+
+    #[test]
+    fn filled_volume_is_a_closed_box() {
+        let mut volume = ValueVolume::new(4, 4, 4);
+        volume.update(&|_, _, _| 2.0);
+        assert_closed(&volume);
+        let mesh = MarchingCubes::new(1.0).mesh_from_volume(&volume);
+        match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => {
+                for p in positions {
+                    assert!(p.iter().all(|c| c.abs() <= 1.5 + f32::EPSILON));
+                }
+            }
+            _ => panic!("expected positions"),
+        }
+    }
+
+    #[test]
+    fn metaballs_are_closed() {
+        let mut volume = ValueVolume::new(16, 16, 16);
+        let balls = [
+            (Vec3::new(-3.0, 2.0, 0.0), 3.0),
+            (Vec3::new(4.0, -2.0, 7.5), 4.0),
+        ];
+        volume.update(&|x, y, z| {
+            balls
+                .iter()
+                .map(|(p, r)| Ball::calc_3d(p, *r, &Kernel::InverseSquare, 1.0, x, y, z))
+                .sum()
+        });
+        assert_closed(&volume);
+    }
+}
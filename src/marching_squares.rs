@@ -1,12 +1,11 @@
 use bevy::prelude::*;
-use bevy::render::{
-    mesh::{Indices, Mesh},
-    render_resource::PrimitiveTopology,
-};
+use bevy::render::mesh::Mesh;
 use std::collections::BTreeMap;
 
-use crate::value_plain::ValuePlain;
+use crate::contour;
+use crate::mesh_attributes::{build_mesh, MeshSettings};
 use crate::threshold_layer::ThresholdLayer;
+use crate::value_plain::ValuePlain;
 
 #[derive(Debug)]
 struct CmpVec3(Vec3);
@@ -26,19 +25,19 @@ impl Eq for CmpVec3 {}
 
 impl PartialOrd<Self> for CmpVec3 {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if self.eq(other) {
-            Some(std::cmp::Ordering::Equal)
-        } else if self.0.x > other.0.x || self.0.y > other.0.y || self.0.z > other.0.z {
-            Some(std::cmp::Ordering::Greater)
-        } else {
-            Some(std::cmp::Ordering::Less)
-        }
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for CmpVec3 {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+        if self.eq(other) {
+            std::cmp::Ordering::Equal
+        } else if self.0.x > other.0.x || self.0.y > other.0.y || self.0.z > other.0.z {
+            std::cmp::Ordering::Greater
+        } else {
+            std::cmp::Ordering::Less
+        }
     }
 }
 
@@ -46,14 +45,55 @@ impl Ord for CmpVec3 {
 pub struct MarchingSquares {
     vertex_index: BTreeMap<CmpVec3, u32>,
     vertices: Vec<[f32; 3]>,
+    values: Vec<f32>,
     indices: Vec<u32>,
+    threshold: f32,
+    settings: MeshSettings,
+    split_squares: bool,
 }
 
 impl MarchingSquares {
-    pub fn from_plain(plain: &ValuePlain, layer: &ThresholdLayer) -> Mesh {
-        Self::default().mesh_from_plain(plain, layer)
+    pub fn with_settings(settings: MeshSettings) -> Self {
+        Self {
+            settings,
+            ..Default::default()
+        }
     }
+
+    /// Emits two triangles per inside cell instead of merging them into
+    /// bigger rectangles, so the result has no T-junctions
+    pub fn split_squares(mut self, split: bool) -> Self {
+        self.split_squares = split;
+        self
+    }
+
     pub fn mesh_from_plain(mut self, plain: &ValuePlain, layer: &ThresholdLayer) -> Mesh {
+        self.march(plain, layer);
+        let mut mesh = build_mesh(
+            self.vertices,
+            self.indices,
+            None,
+            &self.settings,
+            plain.bounds(),
+        );
+        if let Some(ramp) = &self.settings.color_ramp {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, ramp.vertex_colors(&self.values));
+        }
+        mesh
+    }
+
+    /// Vertices, the field value at each vertex and triangle indices
+    pub fn triangles(
+        mut self,
+        plain: &ValuePlain,
+        layer: &ThresholdLayer,
+    ) -> (Vec<[f32; 3]>, Vec<f32>, Vec<u32>) {
+        self.march(plain, layer);
+        (self.vertices, self.values, self.indices)
+    }
+
+    fn march(&mut self, plain: &ValuePlain, layer: &ThresholdLayer) {
+        self.threshold = layer.threshold;
         let quad_amount = (plain.width) * (plain.height);
         let mut quads = vec![false; quad_amount as usize];
 
@@ -122,24 +162,16 @@ impl MarchingSquares {
                 }
             }
         }
-        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
-        mesh.insert_attribute(
-            Mesh::ATTRIBUTE_NORMAL,
-            vec![[0.0, 0.0, 1.0]; self.vertices.len()],
-        );
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; self.vertices.len()]);
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.vertices);
-        mesh.set_indices(Some(Indices::U32(self.indices)));
-        mesh
     }
 
-    fn insert_vertices(&mut self, to_insert: [&Vec3; 3]) {
-        for v in to_insert {
+    fn insert_vertices(&mut self, to_insert: [&(Vec3, f32); 3]) {
+        for (v, value) in to_insert {
             let cv = CmpVec3::new(*v);
             if let Some(i) = self.vertex_index.get(&cv) {
                 self.indices.push(*i);
             } else {
                 self.vertices.push(*v.as_ref());
+                self.values.push(*value);
                 let i = self.vertices.len() as u32 - 1;
                 self.indices.push(i);
                 self.vertex_index.insert(cv, i);
@@ -147,88 +179,79 @@ impl MarchingSquares {
         }
     }
 
-    fn corner(&mut self, plain: &ValuePlain, p1: usize, p2: usize, p3: usize) {
-        let pos_1 = plain.positions[p1];
-        let pos_2 = plain.positions[p2];
-        let pos_3 = plain.positions[p3];
+    fn point(plain: &ValuePlain, p: usize) -> (Vec3, f32) {
+        (plain.positions[p], plain.values[p])
+    }
+
+    /// Point on the edge where the linearly interpolated field equals the
+    /// threshold, with its field value. Only edges with a sign change are
+    /// split, so the value is the threshold itself.
+    fn intersection(&self, plain: &ValuePlain, p1: usize, p2: usize) -> (Vec3, f32) {
+        let pos = contour::crossing(plain, self.threshold, p1, p2);
+        (pos.extend(plain.positions[p1].z), self.threshold)
+    }
 
-        let val_1 = plain.values[p1];
-        let val_2 = plain.values[p2];
-        let val_3 = plain.values[p3];
+    fn corner(&mut self, plain: &ValuePlain, p1: usize, p2: usize, p3: usize) {
+        let point_2 = Self::point(plain, p2);
 
-        let intersection_1 = pos_1.lerp(pos_2, val_2 / (val_1 + val_2));
-        let intersection_2 = pos_2.lerp(pos_3, val_3 / (val_2 + val_3));
+        let intersection_1 = self.intersection(plain, p1, p2);
+        let intersection_2 = self.intersection(plain, p2, p3);
 
-        self.insert_vertices([&intersection_2, &pos_2, &intersection_1]);
+        self.insert_vertices([&intersection_2, &point_2, &intersection_1]);
     }
 
     fn no_corner(&mut self, plain: &ValuePlain, p1: usize, p2: usize, p3: usize, p4: usize) {
-        let pos_1 = plain.positions[p1];
-        let pos_2 = plain.positions[p2];
-        let pos_3 = plain.positions[p3];
-        let pos_4 = plain.positions[p4];
+        let point_2 = Self::point(plain, p2);
+        let point_3 = Self::point(plain, p3);
+        let point_4 = Self::point(plain, p4);
 
-        let val_1 = plain.values[p1];
-        let val_2 = plain.values[p2];
-        let val_4 = plain.values[p4];
+        let intersection_1 = self.intersection(plain, p1, p2);
+        let intersection_2 = self.intersection(plain, p1, p4);
 
-        let intersection_1 = pos_1.lerp(pos_2, val_2 / (val_1 + val_2));
-        let intersection_2 = pos_1.lerp(pos_4, val_4 / (val_1 + val_4));
-
-        self.insert_vertices([&intersection_2, &pos_4, &pos_3]);
-        self.insert_vertices([&intersection_1, &intersection_2, &pos_3]);
-        self.insert_vertices([&pos_2, &intersection_1, &pos_3]);
+        self.insert_vertices([&intersection_2, &point_4, &point_3]);
+        self.insert_vertices([&intersection_1, &intersection_2, &point_3]);
+        self.insert_vertices([&point_2, &intersection_1, &point_3]);
     }
 
     fn split(&mut self, plain: &ValuePlain, p1: usize, p2: usize, p3: usize, p4: usize) {
-        let pos_1 = plain.positions[p1];
-        let pos_2 = plain.positions[p2];
-        let pos_3 = plain.positions[p3];
-        let pos_4 = plain.positions[p4];
-
-        let val_1 = plain.values[p1];
-        let val_2 = plain.values[p2];
-        let val_3 = plain.values[p3];
-        let val_4 = plain.values[p4];
+        let point_3 = Self::point(plain, p3);
+        let point_4 = Self::point(plain, p4);
 
-        let intersection_1 = pos_1.lerp(pos_4, val_4 / (val_1 + val_4));
-        let intersection_2 = pos_2.lerp(pos_3, val_3 / (val_2 + val_3));
+        let intersection_1 = self.intersection(plain, p1, p4);
+        let intersection_2 = self.intersection(plain, p2, p3);
 
-        self.insert_vertices([&intersection_1, &pos_4, &pos_3]);
-        self.insert_vertices([&intersection_2, &intersection_1, &pos_3]);
+        self.insert_vertices([&intersection_1, &point_4, &point_3]);
+        self.insert_vertices([&intersection_2, &intersection_1, &point_3]);
     }
 
     fn diagonal(&mut self, plain: &ValuePlain, p1: usize, p2: usize, p3: usize, p4: usize) {
-        let pos_1 = plain.positions[p1];
-        let pos_2 = plain.positions[p2];
-        let pos_3 = plain.positions[p3];
-        let pos_4 = plain.positions[p4];
-
-        let val_1 = plain.values[p1];
-        let val_2 = plain.values[p2];
-        let val_3 = plain.values[p3];
-        let val_4 = plain.values[p4];
-
-        let intersection_1 = pos_1.lerp(pos_2, val_2 / (val_1 + val_2));
-        let intersection_2 = pos_2.lerp(pos_3, val_3 / (val_2 + val_3));
-        let intersection_3 = pos_3.lerp(pos_4, val_4 / (val_3 + val_4));
-        let intersection_4 = pos_1.lerp(pos_4, val_4 / (val_1 + val_4));
-
-        self.insert_vertices([&intersection_4, &pos_4, &intersection_3]);
-        self.insert_vertices([&intersection_1, &intersection_2, &pos_2]);
+        let point_2 = Self::point(plain, p2);
+        let point_4 = Self::point(plain, p4);
+
+        let intersection_1 = self.intersection(plain, p1, p2);
+        let intersection_2 = self.intersection(plain, p2, p3);
+        let intersection_3 = self.intersection(plain, p3, p4);
+        let intersection_4 = self.intersection(plain, p1, p4);
+
+        self.insert_vertices([&intersection_4, &point_4, &intersection_3]);
+        self.insert_vertices([&intersection_1, &intersection_2, &point_2]);
     }
 
     fn square(
         &mut self,
         plain: &ValuePlain,
         layer: &ThresholdLayer,
-        quads: &mut Vec<bool>,
+        quads: &mut [bool],
         i: u32,
         j: u32,
     ) {
         let mut width = 1;
         let mut height = 1;
 
+        if self.split_squares {
+            return self.quad(plain, quads, i, j, width, height);
+        }
+
         let mut new_i = i + 1;
         let mut next_iso = layer.calculate_iso(plain, new_i, j);
         while next_iso == 15
@@ -257,6 +280,18 @@ impl MarchingSquares {
             height += 1;
             next_iso = layer.calculate_iso(plain, i, new_j);
         }
+        self.quad(plain, quads, i, j, width, height);
+    }
+
+    fn quad(
+        &mut self,
+        plain: &ValuePlain,
+        quads: &mut [bool],
+        i: u32,
+        j: u32,
+        width: u32,
+        height: u32,
+    ) {
         for h in 0..height {
             for w in 0..width {
                 quads[(i + w + (j + h) * plain.width) as usize] = true;
@@ -268,12 +303,69 @@ impl MarchingSquares {
         let p3 = (i + width + (j + height) * plain.width) as usize;
         let p4 = (i + (j + height) * plain.width) as usize;
 
-        let pos_1 = plain.positions[p1];
-        let pos_2 = plain.positions[p2];
-        let pos_3 = plain.positions[p3];
-        let pos_4 = plain.positions[p4];
+        let point_1 = Self::point(plain, p1);
+        let point_2 = Self::point(plain, p2);
+        let point_3 = Self::point(plain, p3);
+        let point_4 = Self::point(plain, p4);
+
+        self.insert_vertices([&point_1, &point_4, &point_3]);
+        self.insert_vertices([&point_2, &point_1, &point_3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossing_value_is_the_threshold() {
+        let mut plain = ValuePlain::new(8, 8);
+        plain.update(&|x, y| 4.0 / (x * x + y * y + 1.0));
+        let squares = MarchingSquares {
+            threshold: 0.5,
+            ..Default::default()
+        };
+        let mut crossings = 0;
+        for j in 0..plain.height {
+            for i in 0..plain.width - 1 {
+                let p1 = (i + j * plain.width) as usize;
+                let p2 = p1 + 1;
+                if (plain.values[p1] > 0.5) == (plain.values[p2] > 0.5) {
+                    continue;
+                }
+                let (pos, value) = squares.intersection(&plain, p1, p2);
+                assert!((value - 0.5).abs() < 1e-5, "{} at {}", value, pos);
+                crossings += 1;
+            }
+        }
+        assert!(crossings > 0);
+    }
 
-        self.insert_vertices([&pos_1, &pos_4, &pos_3]);
-        self.insert_vertices([&pos_2, &pos_1, &pos_3]);
+    #[test]
+    fn signed_field_crosses_on_the_line() {
+        // linear, so the interpolated crossings are exact
+        let mut plain = ValuePlain::new(10, 10);
+        plain.update(&|x, y| x + 0.5 * y - 1.3);
+        let threshold = -0.2;
+        let squares = MarchingSquares {
+            threshold,
+            ..Default::default()
+        };
+        let mut crossings = 0;
+        for j in 0..plain.height - 1 {
+            for i in 0..plain.width - 1 {
+                let p1 = plain.index(i, j);
+                for p2 in [plain.index(i + 1, j), plain.index(i, j + 1)] {
+                    if (plain.values[p1] > threshold) == (plain.values[p2] > threshold) {
+                        continue;
+                    }
+                    let (pos, value) = squares.intersection(&plain, p1, p2);
+                    assert!((pos.x + 0.5 * pos.y - 1.1).abs() < 1e-5, "{}", pos);
+                    assert_eq!(value, threshold);
+                    crossings += 1;
+                }
+            }
+        }
+        assert!(crossings > 0);
     }
 }
@@ -0,0 +1,115 @@
+use bevy::render::mesh::Mesh;
+use std::collections::HashMap;
+
+use crate::contour;
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+use crate::threshold_layer::ThresholdLayer;
+use crate::value_plain::ValuePlain;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Lattice {
+    /// Every cell is split along the same diagonal
+    #[default]
+    Split,
+    /// The diagonal flips every row, so each point has six neighbours like
+    /// on a hexagonal grid
+    Hexagonal,
+}
+
+#[derive(Debug, Default)]
+pub struct MarchingTriangles {
+    vertex_index: HashMap<(usize, usize), u32>,
+    vertices: Vec<[f32; 3]>,
+    values: Vec<f32>,
+    indices: Vec<u32>,
+    settings: MeshSettings,
+    lattice: Lattice,
+}
+
+impl MarchingTriangles {
+    pub fn new(lattice: Lattice) -> Self {
+        Self {
+            lattice,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_settings(mut self, settings: MeshSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn mesh_from_plain(mut self, plain: &ValuePlain, layer: &ThresholdLayer) -> Mesh {
+        for j in 0..(plain.height - 1) {
+            for i in 0..(plain.width - 1) {
+                let [a, d, c, b] = contour::cell_corners(plain, i, j);
+                if self.lattice == Lattice::Hexagonal && j % 2 == 1 {
+                    self.triangle(plain, layer, [a, d, b]);
+                    self.triangle(plain, layer, [d, c, b]);
+                } else {
+                    self.triangle(plain, layer, [a, d, c]);
+                    self.triangle(plain, layer, [a, c, b]);
+                }
+            }
+        }
+
+        let mut mesh = build_mesh(
+            self.vertices,
+            self.indices,
+            None,
+            &self.settings,
+            plain.bounds(),
+        );
+        if let Some(ramp) = &self.settings.color_ramp {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, ramp.vertex_colors(&self.values));
+        }
+        mesh
+    }
+
+    /// Clips a counter clockwise triangle to the inside of the layer,
+    /// there is at most one crossing per edge, so no saddles
+    fn triangle(&mut self, plain: &ValuePlain, layer: &ThresholdLayer, corners: [usize; 3]) {
+        let inside = corners.map(|c| layer.normalized_values[c]);
+        let mut polygon = Vec::with_capacity(4);
+        for k in 0..3 {
+            let p1 = corners[k];
+            let p2 = corners[(k + 1) % 3];
+            if inside[k] {
+                polygon.push(self.insert_vertex(plain, layer, p1, p1));
+            }
+            if inside[k] != inside[(k + 1) % 3] {
+                polygon.push(self.insert_vertex(plain, layer, p1, p2));
+            }
+        }
+        for k in 1..polygon.len().saturating_sub(1) {
+            self.indices
+                .extend([polygon[0], polygon[k], polygon[k + 1]]);
+        }
+    }
+
+    /// Vertex on the edge between two points, or the point itself when
+    /// both are the same
+    fn insert_vertex(
+        &mut self,
+        plain: &ValuePlain,
+        layer: &ThresholdLayer,
+        p1: usize,
+        p2: usize,
+    ) -> u32 {
+        let key = (p1.min(p2), p1.max(p2));
+        if let Some(i) = self.vertex_index.get(&key) {
+            return *i;
+        }
+        let (position, value) = if p1 == p2 {
+            (plain.positions[p1], plain.values[p1])
+        } else {
+            let crossing = contour::crossing(plain, layer.threshold, key.0, key.1);
+            (crossing.extend(plain.positions[p1].z), layer.threshold)
+        };
+        self.vertices.push(position.into());
+        self.values.push(value);
+        let i = self.vertices.len() as u32 - 1;
+        self.vertex_index.insert(key, i);
+        i
+    }
+}
@@ -0,0 +1,135 @@
+use bevy::prelude::*;
+use bevy::render::{
+    mesh::{Indices, Mesh},
+    render_resource::PrimitiveTopology,
+};
+
+use crate::color_ramp::ColorRamp;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UvMode {
+    /// Uvs go from 0 to 1 across the plain bounds
+    #[default]
+    Bounds,
+    /// Uvs are world positions divided by `tile_size`, so textures tile
+    World { tile_size: f32 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshSettings {
+    pub uv_mode: UvMode,
+    pub tangents: bool,
+    /// Writes `ATTRIBUTE_COLOR` sampled from the field value at each vertex
+    pub color_ramp: Option<ColorRamp>,
+}
+
+pub fn build_mesh(
+    vertices: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+    normals: Option<Vec<[f32; 3]>>,
+    settings: &MeshSettings,
+    bounds: (Vec2, Vec2),
+) -> Mesh {
+    let normals = normals.unwrap_or_else(|| compute_normals(&vertices, &indices));
+    let uvs = compute_uvs(&vertices, settings.uv_mode, bounds);
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    if settings.tangents {
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_TANGENT,
+            compute_tangents(&vertices, &normals, &uvs, &indices),
+        );
+    }
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, vertices);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+pub fn compute_normals(vertices: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vec3::ZERO; vertices.len()];
+    for t in indices.chunks_exact(3) {
+        let a = Vec3::from(vertices[t[0] as usize]);
+        let b = Vec3::from(vertices[t[1] as usize]);
+        let c = Vec3::from(vertices[t[2] as usize]);
+        // not normalized, so bigger triangles weigh more
+        let n = (b - a).cross(c - a);
+        for i in t {
+            normals[*i as usize] += n;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|n| {
+            if n.length_squared() > f32::EPSILON {
+                n.normalize().into()
+            } else {
+                [0.0, 0.0, 1.0]
+            }
+        })
+        .collect()
+}
+
+pub fn compute_uvs(vertices: &[[f32; 3]], mode: UvMode, bounds: (Vec2, Vec2)) -> Vec<[f32; 2]> {
+    let (min, max) = bounds;
+    let size = (max - min).max(Vec2::splat(f32::EPSILON));
+    vertices
+        .iter()
+        .map(|v| match mode {
+            UvMode::Bounds => [(v[0] - min.x) / size.x, (max.y - v[1]) / size.y],
+            UvMode::World { tile_size } => [v[0] / tile_size, -v[1] / tile_size],
+        })
+        .collect()
+}
+
+pub fn compute_tangents(
+    vertices: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    let mut tangents = vec![Vec3::ZERO; vertices.len()];
+    let mut bitangents = vec![Vec3::ZERO; vertices.len()];
+    for t in indices.chunks_exact(3) {
+        let (i0, i1, i2) = (t[0] as usize, t[1] as usize, t[2] as usize);
+        let e1 = Vec3::from(vertices[i1]) - Vec3::from(vertices[i0]);
+        let e2 = Vec3::from(vertices[i2]) - Vec3::from(vertices[i0]);
+        let d1 = Vec2::from(uvs[i1]) - Vec2::from(uvs[i0]);
+        let d2 = Vec2::from(uvs[i2]) - Vec2::from(uvs[i0]);
+
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (e1 * d2.y - e2 * d1.y) * r;
+        let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+        for i in [i0, i1, i2] {
+            tangents[i] += tangent;
+            bitangents[i] += bitangent;
+        }
+    }
+
+    tangents
+        .into_iter()
+        .zip(bitangents)
+        .zip(normals.iter())
+        .map(|((t, b), n)| {
+            let n = Vec3::from(*n);
+            // Gram-Schmidt against the normal
+            let mut tangent = t - n * n.dot(t);
+            if tangent.length_squared() < f32::EPSILON {
+                let axis = if n.x.abs() < 0.9 { Vec3::X } else { Vec3::Y };
+                tangent = axis - n * n.dot(axis);
+            }
+            let tangent = tangent.normalize();
+            let w = if n.cross(tangent).dot(b) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            [tangent.x, tangent.y, tangent.z, w]
+        })
+        .collect()
+}
@@ -0,0 +1,419 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const F2: f32 = 0.366_025_42; // (sqrt(3) - 1) / 2
+const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+const F3: f32 = 1.0 / 3.0;
+const G3: f32 = 1.0 / 6.0;
+
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// Seeded noise functions. Lattice points are hashed instead of looked up
+/// in tables, so nothing allocates and the seed is all there is to store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Noise {
+    pub seed: u64,
+}
+
+impl Noise {
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    fn hash(&self, x: i32, y: i32, z: i32) -> u32 {
+        let mut h = (self.seed as u32) ^ ((self.seed >> 32) as u32).rotate_left(16);
+        h ^= (x as u32).wrapping_mul(0x8da6_b343);
+        h ^= (y as u32).wrapping_mul(0xd816_3841);
+        h ^= (z as u32).wrapping_mul(0xcb1a_b31f);
+        h = h.wrapping_mul(0x9e37_79b1);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h
+    }
+
+    fn gradient(&self, x: i32, y: i32, z: i32) -> Vec3 {
+        Vec3::from(GRADIENTS[(self.hash(x, y, z) % 12) as usize])
+    }
+
+    /// 2D simplex noise in about -1..1
+    pub fn simplex2(&self, p: Vec2) -> f32 {
+        let s = (p.x + p.y) * F2;
+        let cell = (p + Vec2::splat(s)).floor();
+        let t = (cell.x + cell.y) * G2;
+        let d0 = p - (cell - Vec2::splat(t));
+        let step = if d0.x > d0.y { Vec2::X } else { Vec2::Y };
+        let corners = [
+            (Vec2::ZERO, d0),
+            (step, d0 - step + Vec2::splat(G2)),
+            (Vec2::ONE, d0 - Vec2::ONE + Vec2::splat(2.0 * G2)),
+        ];
+
+        let (i, j) = (cell.x as i32, cell.y as i32);
+        let mut sum = 0.0;
+        for (offset, d) in corners {
+            let falloff = 0.5 - d.length_squared();
+            if falloff > 0.0 {
+                let g = self.gradient(i + offset.x as i32, j + offset.y as i32, 0);
+                sum += falloff.powi(4) * g.truncate().dot(d);
+            }
+        }
+        70.0 * sum
+    }
+
+    /// 3D simplex noise in about -1..1
+    pub fn simplex3(&self, p: Vec3) -> f32 {
+        let s = (p.x + p.y + p.z) * F3;
+        let cell = (p + Vec3::splat(s)).floor();
+        let t = (cell.x + cell.y + cell.z) * G3;
+        let d0 = p - (cell - Vec3::splat(t));
+
+        // walk from the origin corner along the axes in order of size
+        let (first, second) = if d0.x >= d0.y {
+            if d0.y >= d0.z {
+                (Vec3::X, Vec3::new(1.0, 1.0, 0.0))
+            } else if d0.x >= d0.z {
+                (Vec3::X, Vec3::new(1.0, 0.0, 1.0))
+            } else {
+                (Vec3::Z, Vec3::new(1.0, 0.0, 1.0))
+            }
+        } else if d0.y < d0.z {
+            (Vec3::Z, Vec3::new(0.0, 1.0, 1.0))
+        } else if d0.x < d0.z {
+            (Vec3::Y, Vec3::new(0.0, 1.0, 1.0))
+        } else {
+            (Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+        };
+        let corners = [
+            (Vec3::ZERO, d0),
+            (first, d0 - first + Vec3::splat(G3)),
+            (second, d0 - second + Vec3::splat(2.0 * G3)),
+            (Vec3::ONE, d0 - Vec3::ONE + Vec3::splat(3.0 * G3)),
+        ];
+
+        let (i, j, k) = (cell.x as i32, cell.y as i32, cell.z as i32);
+        let mut sum = 0.0;
+        for (offset, d) in corners {
+            let falloff = 0.6 - d.length_squared();
+            if falloff > 0.0 {
+                let g = self.gradient(
+                    i + offset.x as i32,
+                    j + offset.y as i32,
+                    k + offset.z as i32,
+                );
+                sum += falloff.powi(4) * g.dot(d);
+            }
+        }
+        32.0 * sum
+    }
+
+    /// Distances to the closest and second closest feature point, one
+    /// random point per unit cell
+    pub fn worley2(&self, p: Vec2) -> (f32, f32) {
+        let cell = p.floor();
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (i, j) = (cell.x as i32 + dx, cell.y as i32 + dy);
+                let h = self.hash(i, j, 0);
+                let feature = Vec2::new(i as f32, j as f32) + unit_pair(h);
+                let d = feature.distance(p);
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+        (f1, f2)
+    }
+
+    pub fn worley3(&self, p: Vec3) -> (f32, f32) {
+        let cell = p.floor();
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (i, j, k) = (cell.x as i32 + dx, cell.y as i32 + dy, cell.z as i32 + dz);
+                    let h = self.hash(i, j, k);
+                    let xy = unit_pair(h);
+                    let z = unit_pair(self.hash(k, i, j)).x;
+                    let feature = Vec3::new(i as f32, j as f32, k as f32) + xy.extend(z);
+                    let d = feature.distance(p);
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+/// Two values in 0..1 from the halves of a hash
+fn unit_pair(h: u32) -> Vec2 {
+    Vec2::new((h & 0xffff) as f32, (h >> 16) as f32) / 65536.0
+}
+
+/// Octave settings shared by fBm and ridged noise
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fractal {
+    pub octaves: u32,
+    /// Frequency of the first octave
+    pub frequency: f32,
+    /// Frequency factor between octaves
+    pub lacunarity: f32,
+    /// Amplitude factor between octaves
+    pub gain: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            octaves: 5,
+            frequency: 0.05,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+}
+
+impl Fractal {
+    /// Fractal brownian motion, normalized to the range of `basis`
+    pub fn fbm(&self, basis: impl Fn(Vec3) -> f32, p: Vec3) -> f32 {
+        let (mut sum, mut norm) = (0.0, 0.0);
+        let (mut amplitude, mut frequency) = (1.0, self.frequency);
+        for _ in 0..self.octaves {
+            sum += amplitude * basis(p * frequency);
+            norm += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if norm > 0.0 {
+            sum / norm
+        } else {
+            0.0
+        }
+    }
+
+    /// Ridged multifractal in 0..1 for a basis in -1..1, sharp crests
+    /// where the basis crosses zero, with detail focused on the crests
+    pub fn ridged(&self, basis: impl Fn(Vec3) -> f32, p: Vec3) -> f32 {
+        let (mut sum, mut norm) = (0.0, 0.0);
+        let (mut amplitude, mut frequency) = (1.0, self.frequency);
+        let mut weight = 1.0;
+        for _ in 0..self.octaves {
+            let signal = (1.0 - basis(p * frequency).abs()).powi(2) * weight;
+            weight = (signal * 2.0).clamp(0.0, 1.0);
+            sum += amplitude * signal;
+            norm += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        if norm > 0.0 {
+            sum / norm
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Basis {
+    #[default]
+    Simplex,
+    /// Distance to the closest feature point, round cells
+    Worley,
+    /// Second minus first distance, thin walls between cells
+    WorleyEdges,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Layering {
+    #[default]
+    Fbm,
+    Ridged,
+}
+
+/// Noise field source for `ValuePlain::update`. The z coordinate can be
+/// used as time for animation.
+#[derive(Debug, Clone, Copy, PartialEq, Component, Serialize, Deserialize)]
+pub struct NoiseField {
+    pub noise: Noise,
+    pub basis: Basis,
+    pub layering: Layering,
+    pub fractal: Fractal,
+    /// Distortion of the sample position in world units, 0 turns domain
+    /// warping off
+    pub warp: f32,
+    pub amplitude: f32,
+    pub offset: f32,
+}
+
+impl Default for NoiseField {
+    fn default() -> Self {
+        Self {
+            noise: Noise::default(),
+            basis: Basis::default(),
+            layering: Layering::default(),
+            fractal: Fractal::default(),
+            warp: 0.0,
+            amplitude: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl NoiseField {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.noise = Noise::with_seed(seed);
+        self
+    }
+
+    pub fn with_basis(mut self, basis: Basis) -> Self {
+        self.basis = basis;
+        self
+    }
+
+    pub fn with_layering(mut self, layering: Layering) -> Self {
+        self.layering = layering;
+        self
+    }
+
+    pub fn with_fractal(mut self, fractal: Fractal) -> Self {
+        self.fractal = fractal;
+        self
+    }
+
+    pub fn with_warp(mut self, warp: f32) -> Self {
+        self.warp = warp;
+        self
+    }
+
+    /// Maps the noise to `value * amplitude + offset`
+    pub fn with_range(mut self, amplitude: f32, offset: f32) -> Self {
+        self.amplitude = amplitude;
+        self.offset = offset;
+        self
+    }
+
+    fn basis(&self, p: Vec3) -> f32 {
+        match self.basis {
+            Basis::Simplex => self.noise.simplex3(p),
+            Basis::Worley => self.noise.worley3(p).0,
+            Basis::WorleyEdges => {
+                let (f1, f2) = self.noise.worley3(p);
+                f2 - f1
+            }
+        }
+    }
+
+    pub fn value(&self, x: f32, y: f32, z: f32) -> f32 {
+        let mut p = Vec3::new(x, y, z);
+        if self.warp != 0.0 {
+            // offset by two decorrelated fBm lookups of the simplex noise
+            let simplex = |p: Vec3| self.noise.simplex3(p);
+            let q = Vec2::new(
+                self.fractal.fbm(simplex, p),
+                self.fractal.fbm(
+                    simplex,
+                    p + Vec3::new(5.2, 1.3, 0.0) / self.fractal.frequency,
+                ),
+            );
+            p += (q * self.warp).extend(0.0);
+        }
+        let basis = |p: Vec3| self.basis(p);
+        let value = match self.layering {
+            Layering::Fbm => self.fractal.fbm(basis, p),
+            Layering::Ridged => self.fractal.ridged(basis, p),
+        };
+        value * self.amplitude + self.offset
+    }
+
+    /// The field at depth `z`, for `ValuePlain::update` or `Csg::combine`
+    pub fn field(&self, z: f32) -> impl Fn(f32, f32) -> f32 + '_ {
+        move |x, y| self.value(x, y, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points_3d() -> impl Iterator<Item = Vec3> {
+        (0..4000).map(|i| {
+            let i = i as f32;
+            Vec3::new(i * 0.137, (i * 0.731).sin() * 40.0, i * 0.0191 - 20.0)
+        })
+    }
+
+    #[test]
+    fn simplex_stays_in_range() {
+        let noise = Noise::with_seed(3);
+        let values_2d: Vec<f32> = points_3d().map(|p| noise.simplex2(p.truncate())).collect();
+        let values_3d: Vec<f32> = points_3d().map(|p| noise.simplex3(p)).collect();
+        for values in [values_2d, values_3d] {
+            let min = values.iter().copied().fold(f32::MAX, f32::min);
+            let max = values.iter().copied().fold(f32::MIN, f32::max);
+            assert!(min >= -1.0 && max <= 1.0, "{}..{}", min, max);
+            // and actually uses the range
+            assert!(min < -0.5 && max > 0.5, "{}..{}", min, max);
+        }
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let p = Vec3::new(3.7, -1.2, 8.9);
+        assert_eq!(
+            Noise::with_seed(5).simplex3(p),
+            Noise::with_seed(5).simplex3(p)
+        );
+        assert_ne!(
+            Noise::with_seed(5).simplex3(p),
+            Noise::with_seed(6).simplex3(p)
+        );
+    }
+
+    #[test]
+    fn worley_distances_are_ordered_and_bounded() {
+        let noise = Noise::with_seed(11);
+        for p in points_3d() {
+            let (f1, f2) = noise.worley2(p.truncate());
+            assert!(0.0 <= f1 && f1 <= f2 && f1 <= 2f32.sqrt(), "{} {}", f1, f2);
+            let (f1, f2) = noise.worley3(p);
+            assert!(0.0 <= f1 && f1 <= f2 && f1 <= 3f32.sqrt(), "{} {}", f1, f2);
+        }
+    }
+
+    #[test]
+    fn layerings_stay_in_range() {
+        let field = NoiseField::default().with_seed(2).with_warp(10.0);
+        let ridged = field.with_layering(Layering::Ridged);
+        let mapped = field.with_range(0.25, 0.5);
+        for p in points_3d() {
+            let fbm = field.value(p.x, p.y, p.z);
+            assert!((-1.0..=1.0).contains(&fbm), "{}", fbm);
+            let ridged = ridged.value(p.x, p.y, p.z);
+            assert!((0.0..=1.0).contains(&ridged), "{}", ridged);
+            let mapped = mapped.value(p.x, p.y, p.z);
+            assert!((0.25..=0.75).contains(&mapped), "{}", mapped);
+        }
+    }
+}
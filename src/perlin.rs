@@ -1,9 +1,16 @@
 // use crate::vec3::{Point3, Vec3};
 use bevy::math::Vec3;
+use bevy::prelude::{Component, Vec2};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 pub type Point3 = Vec3;
 
 const PERLIN_POINT_COUNT: u32 = 256;
 
+/// Gradient and permutation tables. Serializing them keeps a field
+/// identical even if the random number generator changes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Perlin {
     random_vec: Vec<Vec3>,
     perm_x: Vec<u32>,
@@ -13,23 +20,31 @@ pub struct Perlin {
 
 impl Perlin {
     pub fn new() -> Self {
+        Self::from_rng(&mut rand::thread_rng())
+    }
+
+    /// Same tables for the same seed on every run and machine
+    pub fn with_seed(seed: u64) -> Self {
+        Self::from_rng(&mut ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    fn from_rng(rng: &mut impl Rng) -> Self {
         use rand::distributions::Distribution;
-        let mut rng = rand::thread_rng();
         let uniform = rand::distributions::Uniform::<f32>::new(-1.0, 1.0);
         Self {
             random_vec: (0..PERLIN_POINT_COUNT)
                 .map(|_| {
                     Vec3::new(
-                        uniform.sample(&mut rng),
-                        uniform.sample(&mut rng),
-                        uniform.sample(&mut rng),
+                        uniform.sample(rng),
+                        uniform.sample(rng),
+                        uniform.sample(rng),
                     )
                     .normalize()
                 })
                 .collect::<Vec<_>>(),
-            perm_x: Self::perlin_generate_perm(),
-            perm_y: Self::perlin_generate_perm(),
-            perm_z: Self::perlin_generate_perm(),
+            perm_x: Self::perlin_generate_perm(rng),
+            perm_y: Self::perlin_generate_perm(rng),
+            perm_z: Self::perlin_generate_perm(rng),
         }
     }
 
@@ -42,12 +57,12 @@ impl Perlin {
         let j = point.y.floor() as i32;
         let k = point.z.floor() as i32;
 
-        let mut c = vec![vec![vec![Vec3::default(); 2]; 2]; 2];
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
 
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    c[di][dj][dk] = self.random_vec[(self.perm_x[((i + di as i32) & 255) as usize]
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.random_vec[(self.perm_x[((i + di as i32) & 255) as usize]
                         ^ self.perm_y[((j + dj as i32) & 255) as usize]
                         ^ self.perm_z[((k + dk as i32) & 255) as usize])
                         as usize]
@@ -59,7 +74,7 @@ impl Perlin {
 
     pub fn turb(&self, point: &Point3, depth: u32) -> f32 {
         let mut accum = 0.0;
-        let mut tmp_p = point.clone();
+        let mut tmp_p = *point;
         let mut weight = 1.0;
         for _ in 0..depth {
             accum += weight * Self::noise(self, &tmp_p);
@@ -69,36 +84,159 @@ impl Perlin {
         accum.abs()
     }
 
-    fn perlin_generate_perm() -> Vec<u32> {
-        let mut p = (0..PERLIN_POINT_COUNT).map(|x| x).collect::<Vec<u32>>();
+    fn perlin_generate_perm(rng: &mut impl Rng) -> Vec<u32> {
+        let mut p = (0..PERLIN_POINT_COUNT).collect::<Vec<u32>>();
 
         use rand::distributions::Distribution;
-        let mut rng = rand::thread_rng();
         for i in (1..PERLIN_POINT_COUNT).rev() {
             let uniform = rand::distributions::Uniform::new(0, i);
-            let target = uniform.sample(&mut rng);
+            let target = uniform.sample(rng);
             p.swap(i as usize, target as usize);
         }
         p
     }
 
-    fn trilinear_interp(c: &Vec<Vec<Vec<Vec3>>>, u: f32, v: f32, w: f32) -> f32 {
+    fn trilinear_interp(c: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
         let uu = u.powi(2) * (3.0 - 2.0 * u);
         let vv = v.powi(2) * (3.0 - 2.0 * v);
         let ww = w.powi(2) * (3.0 - 2.0 * w);
 
         let mut accum: f32 = 0.0;
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
                     let weight = Vec3::new(u - i as f32, v - j as f32, w - k as f32);
                     accum += (i as f32 * uu + (1.0 - i as f32) * (1.0 - uu))
                         * (j as f32 * vv + (1.0 - j as f32) * (1.0 - vv))
                         * (k as f32 * ww + (1.0 - k as f32) * (1.0 - ww))
-                        * c[i][j][k].dot(weight);
+                        * corner.dot(weight);
                 }
             }
         }
         accum
     }
 }
+
+/// Turbulent Perlin noise as a field source for `ValuePlain::update`. The
+/// third noise dimension is time, so the field animates smoothly.
+#[derive(Component)]
+pub struct PerlinField {
+    perlin: Perlin,
+    /// Noise cells per world unit
+    pub scale: f32,
+    /// Shift in world units before scaling
+    pub offset: Vec2,
+    pub octaves: u32,
+    /// Noise units per second along the time dimension
+    pub speed: f32,
+    pub amplitude: f32,
+}
+
+impl Default for PerlinField {
+    fn default() -> Self {
+        Self {
+            perlin: Perlin::new(),
+            scale: 0.05,
+            offset: Vec2::ZERO,
+            octaves: 4,
+            speed: 0.2,
+            amplitude: 1.0,
+        }
+    }
+}
+
+impl PerlinField {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.perlin = Perlin::with_seed(seed);
+        self
+    }
+
+    pub fn with_perlin(mut self, perlin: Perlin) -> Self {
+        self.perlin = perlin;
+        self
+    }
+
+    pub fn perlin(&self) -> &Perlin {
+        &self.perlin
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vec2) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_amplitude(mut self, amplitude: f32) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+
+    pub fn value(&self, x: f32, y: f32, time: f32) -> f32 {
+        let point = Point3::new(
+            (x + self.offset.x) * self.scale,
+            (y + self.offset.y) * self.scale,
+            time * self.speed,
+        );
+        self.amplitude * self.perlin.turb(&point, self.octaves)
+    }
+
+    /// The field frozen at `time`, for `ValuePlain::update` or `Csg::combine`
+    pub fn field(&self, time: f32) -> impl Fn(f32, f32) -> f32 + '_ {
+        move |x, y| self.value(x, y, time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [[f32; 3]; 4] = [
+        [0.3, 0.7, 0.1],
+        [12.25, -3.5, 2.0],
+        [-7.9, 40.1, 0.6],
+        [100.45, 3.3, -5.7],
+    ];
+
+    #[test]
+    fn a_seed_gives_the_same_values_everywhere() {
+        // recorded once, a change here changes every seeded level
+        let expected = [-0.23424116, -0.079746716, 0.08186737, -0.04576101];
+        let perlin = Perlin::with_seed(42);
+        for (p, e) in POINTS.iter().zip(expected) {
+            let value = perlin.noise(&Vec3::from(*p));
+            assert!((value - e).abs() < 1e-6, "{:?}: {} != {}", p, value, e);
+        }
+        assert_eq!(perlin, Perlin::with_seed(42));
+        assert_ne!(perlin, Perlin::with_seed(43));
+    }
+
+    #[test]
+    fn serialized_tables_give_the_same_field() {
+        let field = PerlinField::default().with_seed(7);
+        let json = serde_json::to_string(field.perlin()).unwrap();
+        let perlin: Perlin = serde_json::from_str(&json).unwrap();
+        assert_eq!(&perlin, field.perlin());
+
+        let restored = PerlinField::default().with_perlin(perlin);
+        for p in POINTS {
+            assert_eq!(
+                field.value(p[0], p[1], p[2]),
+                restored.value(p[0], p[1], p[2])
+            );
+        }
+    }
+}
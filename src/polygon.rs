@@ -0,0 +1,456 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+
+use crate::contour::Contour;
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+
+/// Positive for counter clockwise polygons
+pub fn signed_area(points: &[Vec2]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| points[i].perp_dot(points[(i + 1) % n]))
+        .sum::<f32>()
+        * 0.5
+}
+
+pub fn contains_point(points: &[Vec2], p: Vec2) -> bool {
+    let n = points.len();
+    let mut inside = false;
+    for i in 0..n {
+        let a = points[i];
+        let b = points[(i + 1) % n];
+        if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Proper intersection, touching at end points does not count
+pub fn segments_intersect(a1: Vec2, a2: Vec2, b1: Vec2, b2: Vec2) -> bool {
+    let d1 = (a2 - a1).perp_dot(b1 - a1);
+    let d2 = (a2 - a1).perp_dot(b2 - a1);
+    let d3 = (b2 - b1).perp_dot(a1 - b1);
+    let d4 = (b2 - b1).perp_dot(a2 - b1);
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+pub fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((p - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    p.distance(a + ab * t)
+}
+
+/// Closes open contours along the border of `bounds`, where they end.
+/// From the end of a contour the border is followed counter clockwise,
+/// which keeps the inside on the left, up to the next contour start.
+pub fn close_along_border(contours: &[Contour], bounds: (Vec2, Vec2)) -> Vec<Contour> {
+    let (min, max) = bounds;
+    let size = max - min;
+    let perimeter = 2.0 * (size.x + size.y);
+    // distance along the border, counter clockwise from the bottom left
+    let border_position = |p: Vec2| {
+        let sides = [p.y - min.y, max.x - p.x, max.y - p.y, p.x - min.x];
+        let side = (0..4)
+            .min_by(|a, b| sides[*a].abs().total_cmp(&sides[*b].abs()))
+            .unwrap();
+        match side {
+            0 => p.x.clamp(min.x, max.x) - min.x,
+            1 => size.x + p.y.clamp(min.y, max.y) - min.y,
+            2 => size.x + size.y + max.x - p.x.clamp(min.x, max.x),
+            _ => 2.0 * size.x + size.y + max.y - p.y.clamp(min.y, max.y),
+        }
+    };
+    let corners = [
+        (0.0, min),
+        (size.x, Vec2::new(max.x, min.y)),
+        (size.x + size.y, max),
+        (2.0 * size.x + size.y, Vec2::new(min.x, max.y)),
+    ];
+
+    let mut closed = contours
+        .iter()
+        .filter(|c| c.closed)
+        .cloned()
+        .collect::<Vec<_>>();
+    let open = contours
+        .iter()
+        .filter(|c| !c.closed && !c.points.is_empty())
+        .map(|c| {
+            let start = border_position(c.points[0]);
+            let end = border_position(*c.points.last().unwrap());
+            (c, start, end)
+        })
+        .collect::<Vec<_>>();
+
+    let mut used = vec![false; open.len()];
+    for first in 0..open.len() {
+        if used[first] {
+            continue;
+        }
+        let mut points = Vec::new();
+        let mut current = first;
+        loop {
+            used[current] = true;
+            let (contour, _, end) = open[current];
+            points.extend_from_slice(&contour.points);
+            let (next, offset) = open
+                .iter()
+                .enumerate()
+                .map(|(i, (_, start, _))| (i, (start - end).rem_euclid(perimeter)))
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            let mut passed = corners
+                .iter()
+                .map(|(position, corner)| ((position - end).rem_euclid(perimeter), *corner))
+                .filter(|(o, _)| *o > 0.0 && *o < offset)
+                .collect::<Vec<_>>();
+            passed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            points.extend(passed.into_iter().map(|(_, corner)| corner));
+            if used[next] {
+                break;
+            }
+            current = next;
+        }
+        closed.push(Contour {
+            points,
+            closed: true,
+        });
+    }
+    closed
+}
+
+/// Groups contours into polygons, open ones are closed along the border of
+/// `bounds` first. Counter clockwise contours are outlines, clockwise ones
+/// are holes of the smallest outline around them.
+pub fn group(contours: &[Contour], bounds: (Vec2, Vec2)) -> Vec<(Vec<Vec2>, Vec<Vec<Vec2>>)> {
+    let mut outlines = Vec::new();
+    let mut holes = Vec::new();
+    for c in close_along_border(contours, bounds)
+        .into_iter()
+        .filter(|c| c.points.len() >= 3)
+    {
+        let area = signed_area(&c.points);
+        if area > 0.0 {
+            outlines.push((area, c.points, Vec::new()));
+        } else if area < 0.0 {
+            holes.push(c.points);
+        }
+    }
+    outlines.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for hole in holes {
+        if let Some(outline) = outlines
+            .iter_mut()
+            .find(|(_, o, _)| contains_point(o, hole[0]))
+        {
+            outline.2.push(hole);
+        }
+    }
+    outlines
+        .into_iter()
+        .map(|(_, outline, holes)| (outline, holes))
+        .collect()
+}
+
+/// Triangulates contours, holes get cut into their outline and open
+/// contours are closed along the border of `bounds`. Returns the points
+/// and counter clockwise triangle indices.
+pub fn triangulate(contours: &[Contour], bounds: (Vec2, Vec2)) -> (Vec<Vec2>, Vec<u32>) {
+    let mut points = Vec::new();
+    let mut indices = Vec::new();
+    for (outline, holes) in group(contours, bounds) {
+        let polygon = bridge_holes(outline, holes);
+        let first = points.len() as u32;
+        indices.extend(ear_clip(&polygon).into_iter().map(|i| first + i));
+        points.extend(polygon);
+    }
+    (points, indices)
+}
+
+pub fn mesh_from_contours(
+    contours: &[Contour],
+    settings: &MeshSettings,
+    bounds: (Vec2, Vec2),
+) -> Mesh {
+    let (points, indices) = triangulate(contours, bounds);
+    let vertices = points.iter().map(|p| [p.x, p.y, 0.0]).collect();
+    build_mesh(vertices, indices, None, settings, bounds)
+}
+
+/// Whether `direction` leaves `p` into the inside of the polygon, which is
+/// on the left of `prev` -> `p` -> `next`
+fn locally_inside(prev: Vec2, p: Vec2, next: Vec2, direction: Vec2) -> bool {
+    let to_prev = prev - p;
+    let to_next = next - p;
+    if (p - prev).perp_dot(next - p) >= 0.0 {
+        // convex or straight, the inside is the wedge from next round to prev
+        to_next.perp_dot(direction) > 0.0 && direction.perp_dot(to_prev) > 0.0
+    } else {
+        // reflex, the outside is the wedge from prev round to next
+        !(to_prev.perp_dot(direction) >= 0.0 && direction.perp_dot(to_next) >= 0.0)
+    }
+}
+
+/// Whether the segment `u` `v` reaches into the inside of the counter
+/// clockwise triangle, running along its border does not count
+fn segment_enters_triangle(triangle: [Vec2; 3], u: Vec2, v: Vec2) -> bool {
+    let (mut t0, mut t1) = (0.0_f32, 1.0_f32);
+    for k in 0..3 {
+        let (p, q) = (triangle[k], triangle[(k + 1) % 3]);
+        let f0 = (q - p).perp_dot(u - p);
+        let f1 = (q - p).perp_dot(v - p);
+        if f0 < 0.0 && f1 < 0.0 {
+            return false;
+        }
+        // clip to the side where the edge function is positive
+        if f0 < 0.0 {
+            t0 = t0.max(f0 / (f0 - f1));
+        } else if f1 < 0.0 {
+            t1 = t1.min(f0 / (f0 - f1));
+        }
+    }
+    if t0 > t1 {
+        return false;
+    }
+    // the middle of the clipped piece is strictly inside unless the piece
+    // lies on the border
+    let m = u.lerp(v, (t0 + t1) * 0.5);
+    (0..3).all(|k| {
+        let (p, q) = (triangle[k], triangle[(k + 1) % 3]);
+        (q - p).perp_dot(m - p) > 1e-5 * (q - p).length()
+    })
+}
+
+/// Cuts every hole into the outline along a segment that does not cross
+/// anything, which leaves one polygon touching itself along the cuts
+fn bridge_holes(mut outline: Vec<Vec2>, mut holes: Vec<Vec<Vec2>>) -> Vec<Vec2> {
+    // holes further right first, so their bridges do not block later ones
+    let rightmost = |h: &Vec<Vec2>| {
+        h.iter()
+            .enumerate()
+            .max_by(|a, b| a.1.x.total_cmp(&b.1.x))
+            .map(|(i, _)| i)
+            .unwrap()
+    };
+    holes.sort_by(|a, b| b[rightmost(b)].x.total_cmp(&a[rightmost(a)].x));
+
+    for (h, hole) in holes.iter().enumerate() {
+        let m = rightmost(hole);
+        let hole_point = hole[m];
+        let hole_prev = hole[(m + hole.len() - 1) % hole.len()];
+        let hole_next = hole[(m + 1) % hole.len()];
+        let rings = || std::iter::once(&outline).chain(holes[h..].iter());
+        let visible = |b: usize| {
+            let n = outline.len();
+            let p = outline[b];
+            if p == hole_point {
+                return true;
+            }
+            // the cut has to start and end on the inside side of both rings
+            if !locally_inside(
+                outline[(b + n - 1) % n],
+                p,
+                outline[(b + 1) % n],
+                hole_point - p,
+            ) || !locally_inside(hole_prev, hole_point, hole_next, p - hole_point)
+            {
+                return false;
+            }
+            !rings().any(|ring| {
+                ring.iter().enumerate().any(|(i, a)| {
+                    let next = ring[(i + 1) % ring.len()];
+                    segments_intersect(hole_point, p, *a, next)
+                        || (*a != p
+                            && *a != hole_point
+                            && distance_to_segment(*a, hole_point, p) < 1e-5)
+                })
+            })
+        };
+        let bridge = (0..outline.len()).filter(|b| visible(*b)).min_by(|a, b| {
+            outline[*a]
+                .distance_squared(hole_point)
+                .total_cmp(&outline[*b].distance_squared(hole_point))
+        });
+        let bridge = match bridge {
+            Some(b) => b,
+            None => continue,
+        };
+
+        let mut spliced = Vec::with_capacity(outline.len() + hole.len() + 2);
+        spliced.extend_from_slice(&outline[..=bridge]);
+        spliced.extend(hole[m..].iter().chain(hole[..=m].iter()).copied());
+        spliced.extend_from_slice(&outline[bridge..]);
+        outline = spliced;
+    }
+    outline
+}
+
+/// Ear clipping that copes with the polygon touching itself, as it does
+/// along the hole bridges and where contours meet in a sample. A corner is
+/// an ear when no other edge reaches into its triangle.
+fn ear_clip(polygon: &[Vec2]) -> Vec<u32> {
+    let mut remaining = (0..polygon.len()).collect::<Vec<_>>();
+    let mut indices = Vec::with_capacity((polygon.len().saturating_sub(2)) * 3);
+    let mut misses = 0;
+    let mut i = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let (ia, ib, ic) = (
+            remaining[(i + n - 1) % n],
+            remaining[i % n],
+            remaining[(i + 1) % n],
+        );
+        let (a, b, c) = (polygon[ia], polygon[ib], polygon[ic]);
+
+        let turn = (b - a).perp_dot(c - b);
+        if turn.abs() <= f32::EPSILON {
+            // straight or a spike, removing it does not change the area
+            remaining.remove(i % n);
+            i %= remaining.len();
+            continue;
+        }
+        let (min, max) = (a.min(b).min(c), a.max(b).max(c));
+        let ear = turn > 0.0
+            && !remaining
+                .iter()
+                .zip(remaining.iter().cycle().skip(1))
+                .any(|(u, v)| {
+                    let (u, v) = (polygon[*u], polygon[*v]);
+                    u.min(v).cmple(max).all()
+                        && u.max(v).cmpge(min).all()
+                        && segment_enters_triangle([a, b, c], u, v)
+                });
+        if ear {
+            indices.extend([ia as u32, ib as u32, ic as u32]);
+            remaining.remove(i % n);
+            misses = 0;
+        } else if misses > n {
+            // only happens for self intersecting input, drop a point
+            // instead of looping forever
+            debug_assert!(false, "no ear left among {} points", n);
+            remaining.remove(i % n);
+            misses = 0;
+        } else {
+            i += 1;
+            misses += 1;
+        }
+        if !remaining.is_empty() {
+            i %= remaining.len();
+        }
+    }
+    let last = remaining.iter().map(|r| polygon[*r]).collect::<Vec<_>>();
+    if last.len() == 3 && signed_area(&last) > 0.0 {
+        indices.extend(remaining.iter().map(|r| *r as u32));
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contour;
+    use crate::simplify::{self, Simplification};
+    use crate::value_plain::ValuePlain;
+
+    fn group_area(groups: &[(Vec<Vec2>, Vec<Vec<Vec2>>)]) -> f32 {
+        groups
+            .iter()
+            .map(|(outline, holes)| {
+                signed_area(outline) + holes.iter().map(|h| signed_area(h)).sum::<f32>()
+            })
+            .sum()
+    }
+
+    /// Blobs cut by every side of the plain, and one in a corner
+    fn border_plain() -> ValuePlain {
+        let mut plain = ValuePlain::new(40, 30);
+        let blobs = [
+            Vec2::new(-20.0, 3.0),
+            Vec2::new(20.0, -6.0),
+            Vec2::new(4.0, 15.0),
+            Vec2::new(-5.0, -15.0),
+            Vec2::new(20.0, 15.0),
+            Vec2::new(0.0, 0.0),
+        ];
+        plain.update(&|x, y| {
+            blobs
+                .iter()
+                .map(|b| 16.0 / b.distance_squared(Vec2::new(x, y)).max(0.01))
+                .sum()
+        });
+        plain
+    }
+
+    #[test]
+    fn open_contours_are_closed_along_the_border() {
+        let plain = border_plain();
+        let open = contour::contours(&plain, 1.0);
+        assert!(open.iter().filter(|c| !c.closed).count() >= 5);
+
+        let closed = contour::closed_contours(&plain, 1.0);
+        let expected = group_area(&group(&closed, plain.bounds()));
+        let area = group_area(&group(&open, plain.bounds()));
+        assert!((area - expected).abs() < 1e-2, "{} != {}", area, expected);
+    }
+
+    #[test]
+    fn contours_around_the_whole_plain_give_a_hole() {
+        let mut plain = border_plain();
+        for v in plain.values.iter_mut() {
+            *v = 2.0 - *v;
+        }
+        let (min, max) = plain.bounds();
+        let groups = group(&contour::contours(&plain, 1.0), plain.bounds());
+        let expected = (max - min).x * (max - min).y
+            - group_area(&group(
+                &contour::closed_contours(&border_plain(), 1.0),
+                plain.bounds(),
+            ));
+        assert!((group_area(&groups) - expected).abs() < 1e-2);
+    }
+
+    fn triangle_area(points: &[Vec2], indices: &[u32]) -> f32 {
+        indices
+            .chunks(3)
+            .map(|t| {
+                signed_area(&[
+                    points[t[0] as usize],
+                    points[t[1] as usize],
+                    points[t[2] as usize],
+                ])
+            })
+            .sum()
+    }
+
+    #[test]
+    fn triangles_cover_the_simplified_polygons() {
+        // one outline around many wobbly holes
+        let mut plain = ValuePlain::new(40, 40);
+        plain.update(&|x, y| (0.9 * x).sin() * (y + 0.8 * (0.3 * x).sin()).sin());
+        let contours = contour::closed_contours(&plain, -0.2);
+        assert!(group(&contours, plain.bounds())
+            .iter()
+            .any(|(_, holes)| holes.len() > 10));
+
+        for method in [Simplification::DouglasPeucker, Simplification::Visvalingam] {
+            for tolerance in [0.0, 1.5, 3.0] {
+                let simplified = simplify::simplify(&contours, method, tolerance);
+                let expected = group_area(&group(&simplified, plain.bounds()));
+                let (points, indices) = triangulate(&simplified, plain.bounds());
+                let area = triangle_area(&points, &indices);
+                assert!(
+                    (area - expected).abs() < expected * 1e-3,
+                    "{:?} {}: {} != {}",
+                    method,
+                    tolerance,
+                    area,
+                    expected
+                );
+            }
+        }
+    }
+}
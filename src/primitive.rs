@@ -0,0 +1,278 @@
+use bevy::math::Mat2;
+use bevy::prelude::*;
+use std::f32::consts::PI;
+
+use crate::polygon::{contains_point, distance_to_segment};
+
+/// Signed distance shapes, negative inside, in the local space of their
+/// `Primitive`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    Circle {
+        radius: f32,
+    },
+    Box {
+        half_extents: Vec2,
+    },
+    RoundedBox {
+        half_extents: Vec2,
+        radius: f32,
+    },
+    Capsule {
+        a: Vec2,
+        b: Vec2,
+        radius: f32,
+    },
+    /// Zero width, only the distance to it is useful, for example with an
+    /// offset or a smooth union
+    Segment {
+        a: Vec2,
+        b: Vec2,
+    },
+    /// Approximated, exact on the axes and close elsewhere
+    Ellipse {
+        radii: Vec2,
+    },
+    Triangle {
+        points: [Vec2; 3],
+    },
+    Polygon {
+        points: Vec<Vec2>,
+    },
+    Ring {
+        radius: f32,
+        thickness: f32,
+    },
+    Star {
+        radius: f32,
+        inner_radius: f32,
+        points: u32,
+    },
+}
+
+impl Shape {
+    pub fn distance(&self, p: Vec2) -> f32 {
+        match self {
+            Shape::Circle { radius } => p.length() - radius,
+            Shape::Box { half_extents } => box_distance(p, *half_extents),
+            Shape::RoundedBox {
+                half_extents,
+                radius,
+            } => box_distance(p, *half_extents - Vec2::splat(*radius)) - radius,
+            Shape::Capsule { a, b, radius } => distance_to_segment(p, *a, *b) - radius,
+            Shape::Segment { a, b } => distance_to_segment(p, *a, *b),
+            Shape::Ellipse { radii } => {
+                let k0 = (p / *radii).length();
+                let k1 = (p / (*radii * *radii)).length();
+                if k1 > 0.0 {
+                    k0 * (k0 - 1.0) / k1
+                } else {
+                    -radii.min_element()
+                }
+            }
+            Shape::Triangle { points } => polygon_distance(points, p),
+            Shape::Polygon { points } => polygon_distance(points, p),
+            Shape::Ring { radius, thickness } => (p.length() - radius).abs() - thickness * 0.5,
+            Shape::Star {
+                radius,
+                inner_radius,
+                points,
+            } => {
+                // fold into the half wedge between a tip and the next inner
+                // corner, the wedge borders bisect the corners so the single
+                // edge there is the closest one
+                let wedge = PI / (*points).max(2) as f32;
+                let angle = (p.y.atan2(p.x) + wedge).rem_euclid(2.0 * wedge) - wedge;
+                let q = p.length() * Vec2::new(angle.cos(), angle.sin().abs());
+                let tip = Vec2::new(*radius, 0.0);
+                let inner = *inner_radius * Vec2::new(wedge.cos(), wedge.sin());
+                let d = distance_to_segment(q, tip, inner);
+                if (inner - tip).perp_dot(q - tip) > 0.0 {
+                    -d
+                } else {
+                    d
+                }
+            }
+        }
+    }
+}
+
+fn box_distance(p: Vec2, half_extents: Vec2) -> f32 {
+    let d = p.abs() - half_extents;
+    d.max(Vec2::ZERO).length() + d.max_element().min(0.0)
+}
+
+fn polygon_distance(points: &[Vec2], p: Vec2) -> f32 {
+    let n = points.len();
+    let d = (0..n)
+        .map(|i| distance_to_segment(p, points[i], points[(i + 1) % n]))
+        .fold(f32::INFINITY, f32::min);
+    if contains_point(points, p) {
+        -d
+    } else {
+        d
+    }
+}
+
+/// Placed shape contributing to the plain. The field is the negated
+/// distance, so it is positive inside like the metaballs and composes with
+/// the `Csg` operators. Scale is uniform to keep distances exact.
+#[derive(Debug, Clone, PartialEq, Component)]
+pub struct Primitive {
+    pub shape: Shape,
+    pub translation: Vec2,
+    /// Counter clockwise in radians
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+impl Primitive {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            translation: Vec2::ZERO,
+            rotation: 0.0,
+            scale: 1.0,
+        }
+    }
+
+    pub fn with_translation(mut self, translation: Vec2) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn distance(&self, x: f32, y: f32) -> f32 {
+        let local = Mat2::from_angle(-self.rotation) * (Vec2::new(x, y) - self.translation);
+        self.shape.distance(local / self.scale) * self.scale
+    }
+
+    pub fn field(&self, x: f32, y: f32) -> f32 {
+        -self.distance(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_distances(shape: Shape, expected: &[(Vec2, f32)]) {
+        for (p, d) in expected {
+            let distance = shape.distance(*p);
+            assert!(
+                (distance - d).abs() < 1e-4,
+                "{:?} at {}: {}",
+                shape,
+                p,
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn shape_distances() {
+        assert_distances(
+            Shape::Circle { radius: 2.0 },
+            &[(Vec2::ZERO, -2.0), (Vec2::new(3.0, 4.0), 3.0)],
+        );
+        let half_extents = Vec2::new(2.0, 1.0);
+        assert_distances(
+            Shape::Box { half_extents },
+            &[
+                (Vec2::ZERO, -1.0),
+                (Vec2::new(5.0, 0.0), 3.0),
+                (Vec2::new(5.0, 5.0), 5.0),
+            ],
+        );
+        assert_distances(
+            Shape::RoundedBox {
+                half_extents,
+                radius: 0.5,
+            },
+            &[(Vec2::new(5.0, 0.0), 3.0), (Vec2::new(3.6, 3.3), 3.0)],
+        );
+        let (a, b) = (Vec2::new(-2.0, 0.0), Vec2::new(2.0, 0.0));
+        assert_distances(
+            Shape::Capsule { a, b, radius: 1.0 },
+            &[(Vec2::new(0.0, 3.0), 2.0), (Vec2::new(5.0, 0.0), 2.0)],
+        );
+        assert_distances(Shape::Segment { a, b }, &[(Vec2::new(1.0, -3.0), 3.0)]);
+        assert_distances(
+            Shape::Ellipse {
+                radii: Vec2::new(4.0, 2.0),
+            },
+            &[(Vec2::new(6.0, 0.0), 2.0), (Vec2::new(0.0, -3.0), 1.0)],
+        );
+        let points = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(0.0, 4.0),
+        ];
+        assert_distances(
+            Shape::Triangle { points },
+            &[(Vec2::new(1.0, 1.0), -1.0), (Vec2::new(2.0, -2.0), 2.0)],
+        );
+        assert_distances(
+            Shape::Polygon {
+                points: points.to_vec(),
+            },
+            &[(Vec2::new(1.0, 1.0), -1.0), (Vec2::new(-3.0, 2.0), 3.0)],
+        );
+        assert_distances(
+            Shape::Ring {
+                radius: 5.0,
+                thickness: 2.0,
+            },
+            &[(Vec2::ZERO, 4.0), (Vec2::new(0.0, 5.0), -1.0)],
+        );
+    }
+
+    #[test]
+    fn star_tips_and_corners() {
+        let star = Shape::Star {
+            radius: 10.0,
+            inner_radius: 4.0,
+            points: 5,
+        };
+        let wedge = PI / 5.0;
+        for k in 0..5 {
+            let tip = Vec2::new(
+                (2.0 * wedge * k as f32).cos(),
+                (2.0 * wedge * k as f32).sin(),
+            );
+            let inner = Vec2::new(
+                (2.0 * wedge * k as f32 + wedge).cos(),
+                (2.0 * wedge * k as f32 + wedge).sin(),
+            );
+            assert!(star.distance(tip * 10.0).abs() < 1e-4);
+            assert!(star.distance(inner * 4.0).abs() < 1e-4);
+            assert!(star.distance(tip * 7.0) < 0.0);
+            assert!(star.distance(inner * 6.0) > 0.0);
+        }
+        assert!(star.distance(Vec2::ZERO) < 0.0);
+    }
+
+    #[test]
+    fn placed_primitive() {
+        let primitive = Primitive::new(Shape::Box {
+            half_extents: Vec2::new(2.0, 1.0),
+        })
+        .with_translation(Vec2::new(10.0, 0.0))
+        .with_rotation(PI * 0.5)
+        .with_scale(2.0);
+        // rotated upright and doubled, 2 wide and 4 high around (10, 0)
+        assert!((primitive.distance(10.0, 0.0) + 2.0).abs() < 1e-4);
+        assert!((primitive.distance(10.0, 6.0) - 2.0).abs() < 1e-4);
+        assert!((primitive.distance(15.0, 0.0) - 3.0).abs() < 1e-4);
+        assert_eq!(primitive.field(15.0, 0.0), -primitive.distance(15.0, 0.0));
+    }
+}
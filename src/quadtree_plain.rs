@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Mesh;
+use std::collections::HashMap;
+
+use crate::mesh_attributes::{build_mesh, MeshSettings};
+
+/// Point on the lattice in half cell units, so leaf centers of the
+/// smallest cells also have a key
+type Key = (i64, i64);
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    x: u32,
+    y: u32,
+    size: u32,
+    children: Option<usize>,
+}
+
+/// Adaptive alternative to `ValuePlain`. Cells are only split where the
+/// field crosses the threshold or changes fast, so big empty areas cost a
+/// handful of samples. Lattice coordinates go right and up from the bottom
+/// left corner.
+#[derive(Debug, Component)]
+pub struct QuadtreePlain {
+    /// Amount of smallest cells along one side, power of two
+    pub size: u32,
+    pub cell_size: f32,
+    pub threshold: f32,
+    /// Every cell is split at least this many times, so features smaller
+    /// than the root cell are not skipped
+    pub min_depth: u32,
+    /// Cells where the field changes faster than this per world unit are
+    /// split as well
+    pub gradient_limit: f32,
+    nodes: Vec<Node>,
+    samples: HashMap<Key, f32>,
+}
+
+impl QuadtreePlain {
+    pub fn new(size: u32, cell_size: f32, threshold: f32) -> Self {
+        Self {
+            size: size.next_power_of_two(),
+            cell_size,
+            threshold,
+            min_depth: 4,
+            gradient_limit: f32::MAX,
+            nodes: Vec::new(),
+            samples: HashMap::new(),
+        }
+    }
+
+    pub fn with_min_depth(mut self, min_depth: u32) -> Self {
+        self.min_depth = min_depth;
+        self
+    }
+
+    pub fn with_gradient_limit(mut self, gradient_limit: f32) -> Self {
+        self.gradient_limit = gradient_limit;
+        self
+    }
+
+    fn origin(&self) -> Vec2 {
+        Vec2::splat(-(self.size as f32) * self.cell_size * 0.5)
+    }
+
+    fn position(&self, key: Key) -> Vec2 {
+        self.origin() + Vec2::new(key.0 as f32, key.1 as f32) * self.cell_size * 0.5
+    }
+
+    fn sample(&mut self, f: &impl Fn(f32, f32) -> f32, x: u32, y: u32) -> f32 {
+        let key = (x as i64 * 2, y as i64 * 2);
+        if let Some(v) = self.samples.get(&key) {
+            return *v;
+        }
+        let p = self.position(key);
+        let v = f(p.x, p.y);
+        self.samples.insert(key, v);
+        v
+    }
+
+    pub fn update(&mut self, f: &impl Fn(f32, f32) -> f32) {
+        self.nodes.clear();
+        self.samples.clear();
+        self.nodes.push(Node {
+            x: 0,
+            y: 0,
+            size: self.size,
+            children: None,
+        });
+        self.refine(f, 0, 0);
+    }
+
+    fn refine(&mut self, f: &impl Fn(f32, f32) -> f32, node: usize, depth: u32) {
+        let Node { x, y, size, .. } = self.nodes[node];
+        let mut values = vec![
+            self.sample(f, x, y),
+            self.sample(f, x + size, y),
+            self.sample(f, x + size, y + size),
+            self.sample(f, x, y + size),
+        ];
+        if size == 1 {
+            return;
+        }
+        values.push(self.sample(f, x + size / 2, y + size / 2));
+
+        let crosses = values.iter().any(|v| *v > self.threshold)
+            && values.iter().any(|v| *v <= self.threshold);
+        let (min, max) = values.iter().fold((f32::MAX, f32::MIN), |(min, max), v| {
+            (min.min(*v), max.max(*v))
+        });
+        let steep = (max - min) / (size as f32 * self.cell_size) > self.gradient_limit;
+        if depth >= self.min_depth && !crosses && !steep {
+            return;
+        }
+
+        let first = self.nodes.len();
+        let half = size / 2;
+        for (cx, cy) in [(x, y), (x + half, y), (x, y + half), (x + half, y + half)] {
+            self.nodes.push(Node {
+                x: cx,
+                y: cy,
+                size: half,
+                children: None,
+            });
+        }
+        self.nodes[node].children = Some(first);
+        for child in first..(first + 4) {
+            self.refine(f, child, depth + 1);
+        }
+    }
+
+    /// Leaf containing the point given in half cell units
+    fn leaf_at(&self, x2: i64, y2: i64) -> Option<&Node> {
+        if x2 < 0 || y2 < 0 || x2 >= self.size as i64 * 2 || y2 >= self.size as i64 * 2 {
+            return None;
+        }
+        let mut node = self.nodes.first()?;
+        while let Some(first) = node.children {
+            let mid_x = (node.x + node.size / 2) as i64 * 2;
+            let mid_y = (node.y + node.size / 2) as i64 * 2;
+            let child = (x2 >= mid_x) as usize | (((y2 >= mid_y) as usize) << 1);
+            node = &self.nodes[first + child];
+        }
+        Some(node)
+    }
+
+    /// Counter clockwise boundary of a leaf including the corners of smaller
+    /// neighbours lying on its sides
+    fn boundary(&self, leaf: &Node) -> Vec<Key> {
+        let (x, y, s) = (leaf.x as i64, leaf.y as i64, leaf.size as i64);
+        // side start, direction along the side, direction to the outside
+        let sides = [
+            ((x, y), (1, 0), (0, -1)),
+            ((x + s, y), (0, 1), (1, 0)),
+            ((x + s, y + s), (-1, 0), (0, 1)),
+            ((x, y + s), (0, -1), (-1, 0)),
+        ];
+        let mut boundary = Vec::new();
+        for ((sx, sy), (dx, dy), (ox, oy)) in sides {
+            boundary.push((sx * 2, sy * 2));
+            let mut t = 0;
+            while t < s {
+                let px = sx + dx * t;
+                let py = sy + dy * t;
+                let probe = self.leaf_at(2 * px + dx + ox, 2 * py + dy + oy);
+                let step = match probe {
+                    Some(n) if (n.size as i64) < s => {
+                        // distance to the far end of the neighbour along the side
+                        let far = if dx + dy > 0 {
+                            if dx != 0 {
+                                n.x as i64 + n.size as i64 - px
+                            } else {
+                                n.y as i64 + n.size as i64 - py
+                            }
+                        } else if dx != 0 {
+                            px - n.x as i64
+                        } else {
+                            py - n.y as i64
+                        };
+                        far.max(1)
+                    }
+                    _ => s - t,
+                };
+                t += step;
+                if t < s {
+                    boundary.push(((sx + dx * t) * 2, (sy + dy * t) * 2));
+                }
+            }
+        }
+        boundary
+    }
+
+    pub fn mesh(&self, settings: &MeshSettings) -> Mesh {
+        let mut vertex_index = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for leaf in self.nodes.iter().filter(|n| n.children.is_none()) {
+            let boundary = self.boundary(leaf);
+            let values = boundary.iter().map(|k| self.samples[k]).collect::<Vec<_>>();
+            let center = (
+                (leaf.x * 2 + leaf.size) as i64,
+                (leaf.y * 2 + leaf.size) as i64,
+            );
+            let center_value = match self.samples.get(&center) {
+                Some(v) => *v,
+                None => values.iter().sum::<f32>() / values.len() as f32,
+            };
+            if center_value <= self.threshold && values.iter().all(|v| *v <= self.threshold) {
+                continue;
+            }
+
+            for k in 0..boundary.len() {
+                let k2 = (k + 1) % boundary.len();
+                let triangle = [
+                    (center, center_value),
+                    (boundary[k], values[k]),
+                    (boundary[k2], values[k2]),
+                ];
+                self.clip_triangle(&triangle, &mut vertex_index, &mut vertices, &mut indices);
+            }
+        }
+
+        let min = self.origin();
+        let max = min + Vec2::splat(self.size as f32 * self.cell_size);
+        build_mesh(vertices, indices, None, settings, (min, max))
+    }
+
+    /// Same clipping as in `MarchingTriangles`, vertices are shared by
+    /// lattice keys so neighbouring leaves line up without cracks
+    fn clip_triangle(
+        &self,
+        triangle: &[(Key, f32); 3],
+        vertex_index: &mut HashMap<(Key, Key), u32>,
+        vertices: &mut Vec<[f32; 3]>,
+        indices: &mut Vec<u32>,
+    ) {
+        let mut insert = |k1: (Key, f32), k2: (Key, f32)| {
+            let key = if k1.0 <= k2.0 { (k1, k2) } else { (k2, k1) };
+            *vertex_index.entry((key.0 .0, key.1 .0)).or_insert_with(|| {
+                let p1 = self.position(key.0 .0);
+                let p2 = self.position(key.1 .0);
+                let position = if key.0 .0 == key.1 .0 {
+                    p1
+                } else {
+                    let t = ((self.threshold - key.0 .1) / (key.1 .1 - key.0 .1)).clamp(0.0, 1.0);
+                    p1.lerp(p2, t)
+                };
+                vertices.push([position.x, position.y, 0.0]);
+                vertices.len() as u32 - 1
+            })
+        };
+
+        let mut polygon = Vec::with_capacity(4);
+        for k in 0..3 {
+            let a = triangle[k];
+            let b = triangle[(k + 1) % 3];
+            let a_inside = a.1 > self.threshold;
+            if a_inside {
+                polygon.push(insert(a, a));
+            }
+            if a_inside != (b.1 > self.threshold) {
+                polygon.push(insert(a, b));
+            }
+        }
+        for k in 1..polygon.len().saturating_sub(1) {
+            indices.extend([polygon[0], polygon[k], polygon[k + 1]]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::render::mesh::{Indices, VertexAttributeValues};
+
+    fn ball(x: f32, y: f32) -> f32 {
+        25.0 / ((x - 3.3).powi(2) + (y + 7.1).powi(2))
+    }
+
+    #[test]
+    fn gradient_limit_refines_steep_cells() {
+        let mut quadtree = QuadtreePlain::new(64, 1.0, 0.5).with_min_depth(2);
+        quadtree.update(&ball);
+        let coarse = quadtree.nodes.len();
+        quadtree = quadtree.with_gradient_limit(0.2);
+        quadtree.update(&ball);
+        assert!(quadtree.nodes.len() > coarse);
+    }
+
+    #[test]
+    fn refines_only_near_the_contour() {
+        let mut quadtree = QuadtreePlain::new(64, 1.0, 0.5).with_min_depth(2);
+        quadtree.update(&ball);
+        let leaves = quadtree.nodes.iter().filter(|n| n.children.is_none());
+        assert!(leaves.clone().any(|n| n.size == 1));
+        assert!(leaves.clone().any(|n| n.size == 16));
+        assert!(quadtree.samples.len() < 65 * 65 / 4);
+    }
+
+    #[test]
+    fn mesh_has_no_cracks() {
+        // inside leaves stay coarse next to the split contour leaves
+        let mut quadtree = QuadtreePlain::new(64, 1.0, 0.5).with_min_depth(2);
+        quadtree.update(&ball);
+        let mesh = quadtree.mesh(&MeshSettings::default());
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => panic!("expected positions"),
+        };
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices,
+            _ => panic!("expected u32 indices"),
+        };
+        assert!(!indices.is_empty());
+
+        let mut edges = HashMap::new();
+        for t in indices.chunks_exact(3) {
+            for k in 0..3 {
+                *edges.entry((t[k], t[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {:?} is used twice the same way", (a, b));
+            if edges.contains_key(&(b, a)) {
+                continue;
+            }
+            // an edge without a neighbour lies on the contour, a vertex of
+            // another triangle inside it would be a T-junction
+            let a = Vec3::from(positions[a as usize]).truncate();
+            let b = Vec3::from(positions[b as usize]).truncate();
+            for p in positions.iter().map(|p| Vec3::from(*p).truncate()) {
+                let t = (p - a).dot(b - a) / (b - a).length_squared();
+                let off_line = (p - a).perp_dot(b - a).abs() / (b - a).length();
+                assert!(
+                    t <= 1e-3 || t >= 1.0 - 1e-3 || off_line > 1e-3,
+                    "{} splits the edge from {} to {}",
+                    p,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+
+use crate::value_plain::ValuePlain;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub point: Vec2,
+    /// Points out of the inside
+    pub normal: Vec2,
+    pub distance: f32,
+}
+
+/// First crossing of `threshold` along the ray. Walks the cells the ray
+/// passes (DDA) and solves the bilinear field of each cell exactly, so
+/// hits lie on the same curve the field describes.
+pub fn raycast(
+    plain: &ValuePlain,
+    threshold: f32,
+    origin: Vec2,
+    direction: Vec2,
+    max_distance: f32,
+) -> Option<RayHit> {
+    let direction = direction.try_normalize()?;
+    let spacing = plain.spacing();
+    // t stays in world units, the ray is only expressed in grid coordinates
+    let o = plain.grid_coords(origin);
+    let d = direction / spacing;
+    let max = Vec2::new(plain.width as f32 - 1.0, plain.height as f32 - 1.0);
+
+    // clip the ray to the plain
+    let (mut t_min, mut t_max) = (0.0_f32, max_distance);
+    for axis in 0..2 {
+        if d[axis].abs() < f32::EPSILON {
+            if o[axis] < 0.0 || o[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = -o[axis] / d[axis];
+        let t2 = (max[axis] - o[axis]) / d[axis];
+        t_min = t_min.max(t1.min(t2));
+        t_max = t_max.min(t1.max(t2));
+    }
+    if t_min > t_max {
+        return None;
+    }
+
+    let start = o + d * t_min;
+    let last = max - Vec2::ONE;
+    let mut cell = start.floor().clamp(Vec2::ZERO, last);
+    let step = d.signum();
+    // distance to the next cell border on each axis, and between borders
+    let mut t_next = Vec2::ZERO;
+    let mut t_delta = Vec2::ZERO;
+    for axis in 0..2 {
+        if d[axis].abs() < f32::EPSILON {
+            t_next[axis] = f32::INFINITY;
+            t_delta[axis] = f32::INFINITY;
+        } else {
+            let border = cell[axis] + if d[axis] > 0.0 { 1.0 } else { 0.0 };
+            t_next[axis] = (border - o[axis]) / d[axis];
+            t_delta[axis] = 1.0 / d[axis].abs();
+        }
+    }
+
+    let mut t_enter = t_min;
+    while t_enter <= t_max {
+        let t_exit = t_next.min_element().min(t_max);
+        let (i, j) = (cell.x as u32, cell.y as u32);
+        if let Some(t) = cell_crossing(plain, threshold, i, j, (o, d), (t_enter, t_exit)) {
+            let point = origin + direction * t;
+            let normal = -cell_gradient(plain, i, j, o + d * t) / spacing;
+            return Some(RayHit {
+                point,
+                normal: normal.normalize_or_zero(),
+                distance: t,
+            });
+        }
+
+        let axis = if t_next.x < t_next.y { 0 } else { 1 };
+        cell[axis] += step[axis];
+        if cell[axis] < 0.0 || cell[axis] > last[axis] {
+            return None;
+        }
+        t_enter = t_exit;
+        t_next[axis] += t_delta[axis];
+    }
+    None
+}
+
+/// Corner values of cell (i, j): top left, top right, bottom left, bottom right
+fn cell_values(plain: &ValuePlain, i: u32, j: u32) -> [f32; 4] {
+    [(i, j), (i + 1, j), (i, j + 1), (i + 1, j + 1)].map(|(i, j)| plain.values[plain.index(i, j)])
+}
+
+/// Smallest t in the range where the bilinear field of the cell along the
+/// ray equals the threshold
+fn cell_crossing(
+    plain: &ValuePlain,
+    threshold: f32,
+    i: u32,
+    j: u32,
+    (o, d): (Vec2, Vec2),
+    (t_enter, t_exit): (f32, f32),
+) -> Option<f32> {
+    let [f00, f10, f01, f11] = cell_values(plain, i, j);
+    let (a, b, c) = (f10 - f00, f01 - f00, f00 - f10 - f01 + f11);
+    let s0 = o.x - i as f32;
+    let r0 = o.y - j as f32;
+
+    // f(t) = qa t² + qb t + qc
+    let qa = c * d.x * d.y;
+    let qb = a * d.x + b * d.y + c * (s0 * d.y + r0 * d.x);
+    let qc = f00 - threshold + a * s0 + b * r0 + c * s0 * r0;
+
+    let in_range = |t: &f32| *t >= t_enter && *t <= t_exit;
+    if qa.abs() < f32::EPSILON {
+        if qb.abs() < f32::EPSILON {
+            return None;
+        }
+        return Some(-qc / qb).filter(in_range);
+    }
+    let discriminant = qb * qb - 4.0 * qa * qc;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let root = discriminant.sqrt();
+    let t1 = (-qb - root) / (2.0 * qa);
+    let t2 = (-qb + root) / (2.0 * qa);
+    [t1.min(t2), t1.max(t2)].into_iter().find(in_range)
+}
+
+/// Gradient of the bilinear field of the cell in grid coordinates
+fn cell_gradient(plain: &ValuePlain, i: u32, j: u32, g: Vec2) -> Vec2 {
+    let [f00, f10, f01, f11] = cell_values(plain, i, j);
+    let s = g.x - i as f32;
+    let r = g.y - j as f32;
+    Vec2::new(
+        (f10 - f00) * (1.0 - r) + (f11 - f01) * r,
+        (f01 - f00) * (1.0 - s) + (f11 - f10) * s,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Circle of radius 10 around the origin, positive inside
+    fn circle() -> ValuePlain {
+        let mut plain = ValuePlain::new(40, 40);
+        plain.update(&|x, y| 10.0 - Vec2::new(x, y).length());
+        plain
+    }
+
+    #[test]
+    fn hits_the_circle() {
+        let plain = circle();
+        for angle in [0.0_f32, 0.4, 1.3, 2.5, 4.0, 5.5] {
+            let outward = Vec2::new(angle.cos(), angle.sin());
+            let origin = outward * 18.0 + outward.perp() * 2.0;
+            let hit = raycast(&plain, 0.0, origin, -outward, 50.0).unwrap();
+
+            let expected = outward * (100.0_f32 - 4.0).sqrt() + outward.perp() * 2.0;
+            assert!(
+                hit.point.distance(expected) < 0.05,
+                "{} {}",
+                hit.point,
+                expected
+            );
+            assert!((hit.distance - origin.distance(hit.point)).abs() < 1e-3);
+            let normal = expected.normalize();
+            assert!(hit.normal.dot(normal) > 0.999, "{} {}", hit.normal, normal);
+        }
+    }
+
+    #[test]
+    fn misses() {
+        let plain = circle();
+        let origin = Vec2::new(-15.0, 0.0);
+        // pointing away, passing by and stopping short
+        assert!(raycast(&plain, 0.0, origin, Vec2::new(-1.0, 0.0), 50.0).is_none());
+        assert!(raycast(&plain, 0.0, Vec2::new(-15.0, 12.0), Vec2::X, 50.0).is_none());
+        assert!(raycast(&plain, 0.0, origin, Vec2::X, 4.0).is_none());
+        // starting outside of the plain
+        let hit = raycast(&plain, 0.0, Vec2::new(-40.0, 0.3), Vec2::X, 50.0).unwrap();
+        assert!((hit.point.x + 10.0).abs() < 0.05, "{}", hit.point);
+    }
+}
@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+
+use crate::contour::Contour;
+use crate::polygon;
+
+/// Connected inside area of a layer, measured on its contours
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Region {
+    pub area: f32,
+    pub perimeter: f32,
+    pub centroid: Vec2,
+    pub min: Vec2,
+    pub max: Vec2,
+    /// Counter clockwise
+    pub outline: Vec<Vec2>,
+    /// Clockwise
+    pub holes: Vec<Vec<Vec2>>,
+}
+
+impl Region {
+    pub fn from_polygon(outline: Vec<Vec2>, holes: Vec<Vec<Vec2>>) -> Self {
+        let mut area = 0.0;
+        let mut moment = Vec2::ZERO;
+        let mut perimeter = 0.0;
+        // holes are clockwise, so their area and moment come out negative
+        for ring in std::iter::once(&outline).chain(holes.iter()) {
+            let n = ring.len();
+            for i in 0..n {
+                let a = ring[i];
+                let b = ring[(i + 1) % n];
+                let cross = a.perp_dot(b);
+                area += cross * 0.5;
+                moment += (a + b) * cross / 6.0;
+                perimeter += a.distance(b);
+            }
+        }
+        let (min, max) = outline.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        );
+        let centroid = if area.abs() > f32::EPSILON {
+            moment / area
+        } else {
+            (min + max) * 0.5
+        };
+        Self {
+            area,
+            perimeter,
+            centroid,
+            min,
+            max,
+            outline,
+            holes,
+        }
+    }
+
+    pub fn hole_count(&self) -> usize {
+        self.holes.len()
+    }
+}
+
+/// Regions of the contours, open contours are closed along the border of
+/// `bounds`
+pub fn regions(contours: &[Contour], bounds: (Vec2, Vec2)) -> Vec<Region> {
+    polygon::group(contours, bounds)
+        .into_iter()
+        .map(|(outline, holes)| Region::from_polygon(outline, holes))
+        .collect()
+}
+
+/// Regions of the layer on the same entity, refreshed every frame when
+/// present
+#[derive(Debug, Clone, Default, Component)]
+pub struct Regions(pub Vec<Region>);
+
+impl Regions {
+    pub fn largest(&self) -> Option<&Region> {
+        self.0.iter().max_by(|a, b| a.area.total_cmp(&b.area))
+    }
+
+    pub fn total_area(&self) -> f32 {
+        self.0.iter().map(|r| r.area).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contour;
+    use crate::value_plain::ValuePlain;
+
+    fn square(min: Vec2, max: Vec2) -> Vec<Vec2> {
+        vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)]
+    }
+
+    #[test]
+    fn square_with_a_hole() {
+        let outline = square(Vec2::new(0.0, 0.0), Vec2::new(4.0, 4.0));
+        let mut hole = square(Vec2::new(2.0, 1.0), Vec2::new(3.0, 2.0));
+        hole.reverse();
+        let region = Region::from_polygon(outline, vec![hole]);
+
+        assert_eq!(region.area, 15.0);
+        assert_eq!(region.perimeter, 20.0);
+        assert_eq!(region.min, Vec2::new(0.0, 0.0));
+        assert_eq!(region.max, Vec2::new(4.0, 4.0));
+        assert_eq!(region.hole_count(), 1);
+        // (16 * (2, 2) - 1 * (2.5, 1.5)) / 15
+        let centroid = Vec2::new(29.5, 30.5) / 15.0;
+        assert!(
+            region.centroid.distance(centroid) < 1e-5,
+            "{}",
+            region.centroid
+        );
+    }
+
+    #[test]
+    fn one_region_per_blob() {
+        let mut plain = ValuePlain::new(40, 20);
+        plain.update(&|x, y| {
+            let a = 9.0 / ((x + 10.0).powi(2) + y * y);
+            let b = 4.0 / ((x - 10.0).powi(2) + y * y);
+            a + b
+        });
+        let regions = Regions(regions(
+            &contour::closed_contours(&plain, 0.5),
+            plain.bounds(),
+        ));
+        assert_eq!(regions.0.len(), 2);
+
+        // circles of radius sqrt(18) and sqrt(8), slightly grown by the
+        // other ball
+        let largest = regions.largest().unwrap();
+        assert!(largest.centroid.distance(Vec2::new(-10.0, 0.0)) < 0.5);
+        assert!((largest.area - 18.0 * std::f32::consts::PI).abs() < 3.0);
+        let total = 26.0 * std::f32::consts::PI;
+        assert!((regions.total_area() - total).abs() < 5.0);
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+
+use crate::contour;
+use crate::polygon::distance_to_segment;
+use crate::value_plain::ValuePlain;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMethod {
+    /// Distance to every contour segment, exact but slow on big plains
+    #[default]
+    Exact,
+    /// Seeds the samples next to the contour and sweeps the closest segment
+    /// through the plain, close to exact for a fraction of the cost
+    Sweeping,
+}
+
+/// Signed distance to the `threshold` contour of the plain, positive
+/// inside and negative outside, so the region at threshold 0 is the same
+/// and other thresholds offset it by that distance. The border of the
+/// plain is no contour, samples without any contour get infinity.
+pub fn signed_distance(plain: &ValuePlain, threshold: f32, method: DistanceMethod) -> ValuePlain {
+    let segments = contour::segments(plain, threshold);
+    let distances = match method {
+        DistanceMethod::Exact => plain
+            .positions
+            .iter()
+            .map(|p| {
+                segments
+                    .iter()
+                    .map(|(a, b)| distance_to_segment(p.truncate(), *a, *b))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .collect(),
+        DistanceMethod::Sweeping => sweep(plain, threshold),
+    };
+
+    let mut field = plain.clone();
+    for (v, d) in field.values.iter_mut().zip(distances) {
+        *v = if *v > threshold { d } else { -d };
+    }
+    field
+}
+
+fn sweep(plain: &ValuePlain, threshold: f32) -> Vec<f32> {
+    let (width, height) = (plain.width as i64, plain.height as i64);
+    let mut segments = Vec::new();
+    let mut closest = vec![None; plain.values.len()];
+    let mut distances = vec![f32::INFINITY; plain.values.len()];
+
+    let mut consider =
+        |p: usize, s: usize, segments: &[(Vec2, Vec2)], closest: &mut [Option<usize>]| {
+            let (a, b) = segments[s];
+            let d = distance_to_segment(plain.positions[p].truncate(), a, b);
+            if d < distances[p] {
+                distances[p] = d;
+                closest[p] = Some(s);
+            }
+        };
+
+    // corners of every crossed cell start with the segments of that cell
+    for j in 0..(plain.height - 1) {
+        for i in 0..(plain.width - 1) {
+            let first = segments.len();
+            contour::cell_segments(plain, threshold, i, j, &mut segments);
+            for s in first..segments.len() {
+                for c in contour::cell_corners(plain, i, j) {
+                    consider(c, s, &segments, &mut closest);
+                }
+            }
+        }
+    }
+
+    let forward = [(-1, 0), (0, -1), (-1, -1), (1, -1)];
+    let backward = forward.map(|(di, dj)| (-di, -dj));
+    for _ in 0..2 {
+        for (neighbours, reverse) in [(forward, false), (backward, true)] {
+            for n in 0..(width * height) {
+                let n = if reverse { width * height - 1 - n } else { n };
+                let (i, j) = (n % width, n / width);
+                for (di, dj) in neighbours {
+                    let (ni, nj) = (i + di, j + dj);
+                    if ni < 0 || nj < 0 || ni >= width || nj >= height {
+                        continue;
+                    }
+                    if let Some(s) = closest[(ni + nj * width) as usize] {
+                        consider(n as usize, s, &segments, &mut closest);
+                    }
+                }
+            }
+        }
+    }
+    distances
+}
+
+/// Field whose region above `threshold` is the region of the plain grown
+/// by `distance`, negative distances shrink it. Feeds straight into the
+/// meshers with the same threshold.
+pub fn grow(
+    plain: &ValuePlain,
+    threshold: f32,
+    distance: f32,
+    method: DistanceMethod,
+) -> ValuePlain {
+    let mut field = signed_distance(plain, threshold, method);
+    for v in field.values.iter_mut() {
+        *v += threshold + distance;
+    }
+    field
+}
+
+pub fn shrink(
+    plain: &ValuePlain,
+    threshold: f32,
+    distance: f32,
+    method: DistanceMethod,
+) -> ValuePlain {
+    grow(plain, threshold, -distance, method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Field of a circle with radius 10 that is no distance, so the
+    /// distance has to come from the contour
+    fn circle() -> ValuePlain {
+        let mut plain = ValuePlain::new(40, 40);
+        plain.update(&|x, y| 100.0 / (x * x + y * y).max(0.01));
+        plain
+    }
+
+    #[test]
+    fn exact_distance_of_a_circle() {
+        let plain = circle();
+        let field = signed_distance(&plain, 1.0, DistanceMethod::Exact);
+        for (p, d) in plain.positions.iter().zip(field.values.iter()) {
+            let expected = 10.0 - p.truncate().length();
+            assert!((d - expected).abs() < 0.05, "{} at {}", d, p);
+        }
+    }
+
+    #[test]
+    fn sweeping_is_close_to_exact() {
+        let plain = circle();
+        let exact = signed_distance(&plain, 1.0, DistanceMethod::Exact);
+        let sweeping = signed_distance(&plain, 1.0, DistanceMethod::Sweeping);
+        for (e, s) in exact.values.iter().zip(sweeping.values.iter()) {
+            // the swept segment is some segment, never closer than the closest
+            assert!(s.abs() >= e.abs() - 1e-5, "{} < {}", s, e);
+            assert!((e - s).abs() < 0.1, "{} != {}", s, e);
+        }
+    }
+
+    #[test]
+    fn no_contour_is_infinitely_far() {
+        let mut plain = ValuePlain::new(4, 4);
+        plain.update(&|_, _| 2.0);
+        let field = signed_distance(&plain, 1.0, DistanceMethod::Sweeping);
+        assert!(field.values.iter().all(|v| *v == f32::INFINITY));
+    }
+
+    #[test]
+    fn grow_and_shrink_move_the_circle() {
+        let plain = circle();
+        for (field, radius) in [
+            (grow(&plain, 1.0, 2.0, DistanceMethod::Exact), 12.0),
+            (shrink(&plain, 1.0, 3.0, DistanceMethod::Exact), 7.0),
+        ] {
+            for (p, v) in field.positions.iter().zip(field.values.iter()) {
+                // distance to the new contour, still on the original threshold
+                let expected = radius - p.truncate().length() + 1.0;
+                assert!((v - expected).abs() < 0.05, "{} at {}", v, p);
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use bevy::prelude::*;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::contour::Contour;
+use crate::polygon::{contains_point, distance_to_segment, segments_intersect};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Simplification {
+    /// Keeps splitting at the point furthest from the shortcut
+    DouglasPeucker,
+    /// Keeps removing the point spanning the smallest triangle
+    Visvalingam,
+}
+
+/// Removes points deviating less than `tolerance` from the simplified
+/// contour. Shortcuts that would cross another segment or jump over any
+/// other point are refused, so contours never intersect and islands never
+/// switch sides.
+pub fn simplify(contours: &[Contour], method: Simplification, tolerance: f32) -> Vec<Contour> {
+    let mut simplifier = Simplifier::new(contours);
+    match method {
+        Simplification::DouglasPeucker => simplifier.douglas_peucker(tolerance),
+        Simplification::Visvalingam => simplifier.visvalingam(tolerance),
+    }
+    simplifier.result()
+}
+
+struct Simplifier<'a> {
+    contours: &'a [Contour],
+    kept: Vec<Vec<bool>>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Candidate {
+    area: f32,
+    contour: usize,
+    point: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // smallest area first in the max heap
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.area.total_cmp(&self.area)
+    }
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(contours: &'a [Contour]) -> Self {
+        Self {
+            contours,
+            kept: contours
+                .iter()
+                .map(|c| vec![true; c.points.len()])
+                .collect(),
+        }
+    }
+
+    fn result(&self) -> Vec<Contour> {
+        self.contours
+            .iter()
+            .zip(self.kept.iter())
+            .map(|(c, kept)| Contour {
+                points: c
+                    .points
+                    .iter()
+                    .zip(kept.iter())
+                    .filter(|(_, k)| **k)
+                    .map(|(p, _)| *p)
+                    .collect(),
+                closed: c.closed,
+            })
+            .collect()
+    }
+
+    fn min_points(contour: &Contour) -> usize {
+        if contour.closed {
+            3
+        } else {
+            2
+        }
+    }
+
+    /// Points of contour `c` from `i` to `j` going forward
+    fn span(&self, c: usize, i: usize, j: usize) -> Vec<usize> {
+        let n = self.contours[c].points.len();
+        let len = (j + n - i) % n;
+        let len = if len == 0 { n } else { len };
+        (0..=len).map(|k| (i + k) % n).collect()
+    }
+
+    /// Whether the points of `span` can be replaced by a straight segment
+    /// between its ends without touching anything else
+    fn shortcut_allowed(&self, c: usize, span: &[usize]) -> bool {
+        let points = &self.contours[c].points;
+        let first = span[0];
+        let last = *span.last().unwrap();
+        let a = points[first];
+        let b = points[last];
+        let region = span.iter().map(|p| points[*p]).collect::<Vec<_>>();
+
+        for (other, contour) in self.contours.iter().enumerate() {
+            let kept = contour
+                .points
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| self.kept[other][*i])
+                .collect::<Vec<_>>();
+            for (k, (i, p)) in kept.iter().enumerate() {
+                let in_span = other == c && span.contains(i);
+                if !in_span && region.len() > 3 && contains_point(&region, **p) {
+                    return false;
+                }
+                if !in_span && region.len() == 3 && triangle_has(&region, **p) {
+                    return false;
+                }
+                if k + 1 == kept.len() && !contour.closed {
+                    continue;
+                }
+                let q = kept[(k + 1) % kept.len()].1;
+                if segments_intersect(a, b, **p, *q) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn douglas_peucker(&mut self, tolerance: f32) {
+        for (c, contour) in self.contours.iter().enumerate() {
+            let n = contour.points.len();
+            if n <= Self::min_points(contour) {
+                continue;
+            }
+            self.kept[c].iter_mut().for_each(|k| *k = false);
+            self.kept[c][0] = true;
+            self.kept[c][n - 1] = true;
+            if contour.closed {
+                self.kept[c][n / 3] = true;
+                self.kept[c][2 * n / 3] = true;
+            }
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for c in 0..self.contours.len() {
+                let contour = &self.contours[c];
+                let kept = (0..contour.points.len())
+                    .filter(|i| self.kept[c][*i])
+                    .collect::<Vec<_>>();
+                let pairs = if contour.closed {
+                    kept.len()
+                } else {
+                    kept.len().saturating_sub(1)
+                };
+                for k in 0..pairs {
+                    let span = self.span(c, kept[k], kept[(k + 1) % kept.len()]);
+                    if span.len() <= 2 {
+                        continue;
+                    }
+                    let a = contour.points[span[0]];
+                    let b = contour.points[*span.last().unwrap()];
+                    let (furthest, deviation) = span[1..span.len() - 1]
+                        .iter()
+                        .map(|p| (*p, distance_to_segment(contour.points[*p], a, b)))
+                        .max_by(|x, y| x.1.total_cmp(&y.1))
+                        .unwrap();
+                    if deviation > tolerance || !self.shortcut_allowed(c, &span) {
+                        self.kept[c][furthest] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn neighbours(&self, c: usize, point: usize) -> Option<(usize, usize)> {
+        let contour = &self.contours[c];
+        let n = contour.points.len();
+        let step = |mut i: usize, forward: bool| loop {
+            if !contour.closed && ((forward && i == n - 1) || (!forward && i == 0)) {
+                return None;
+            }
+            i = if forward {
+                (i + 1) % n
+            } else {
+                (i + n - 1) % n
+            };
+            if i == point {
+                return None;
+            }
+            if self.kept[c][i] {
+                return Some(i);
+            }
+        };
+        Some((step(point, false)?, step(point, true)?))
+    }
+
+    fn area(&self, c: usize, point: usize) -> Option<f32> {
+        let (prev, next) = self.neighbours(c, point)?;
+        let points = &self.contours[c].points;
+        let (a, b, p) = (points[prev], points[next], points[point]);
+        Some((b - a).perp_dot(p - a).abs() * 0.5)
+    }
+
+    fn visvalingam(&mut self, tolerance: f32) {
+        let mut heap = BinaryHeap::new();
+        let mut counts = self
+            .contours
+            .iter()
+            .map(|c| c.points.len())
+            .collect::<Vec<_>>();
+        for c in 0..self.contours.len() {
+            for point in 0..self.contours[c].points.len() {
+                if let Some(area) = self.area(c, point) {
+                    heap.push(Candidate {
+                        area,
+                        contour: c,
+                        point,
+                    });
+                }
+            }
+        }
+
+        while let Some(Candidate {
+            area,
+            contour: c,
+            point,
+        }) = heap.pop()
+        {
+            if !self.kept[c][point] || counts[c] <= Self::min_points(&self.contours[c]) {
+                continue;
+            }
+            // neighbours were removed since this was pushed
+            match self.area(c, point) {
+                Some(current) if current != area => {
+                    heap.push(Candidate {
+                        area: current,
+                        contour: c,
+                        point,
+                    });
+                    continue;
+                }
+                None => continue,
+                _ => {}
+            }
+
+            let (prev, next) = self.neighbours(c, point).unwrap();
+            let points = &self.contours[c].points;
+            if distance_to_segment(points[point], points[prev], points[next]) > tolerance {
+                continue;
+            }
+            if !self.shortcut_allowed(c, &[prev, point, next]) {
+                continue;
+            }
+            self.kept[c][point] = false;
+            counts[c] -= 1;
+            for neighbour in [prev, next] {
+                if let Some(area) = self.area(c, neighbour) {
+                    heap.push(Candidate {
+                        area,
+                        contour: c,
+                        point: neighbour,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn triangle_has(triangle: &[Vec2], p: Vec2) -> bool {
+    let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+    let d1 = (b - a).perp_dot(p - a);
+    let d2 = (c - b).perp_dot(p - b);
+    let d3 = (a - c).perp_dot(p - c);
+    let negative = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+    let positive = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    !(negative && positive)
+}
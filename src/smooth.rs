@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+
+use crate::contour::Contour;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    /// Cuts every corner at a quarter of its edges, each iteration doubles
+    /// the amount of points
+    Chaikin { iterations: u32 },
+    /// Resamples a centripetal Catmull-Rom spline through the points, so
+    /// points end up about `spacing` apart
+    CatmullRom { spacing: f32 },
+}
+
+/// Smooths contours. End points of open contours stay where they are, so
+/// they still meet the border of the plain.
+pub fn smooth(contours: &[Contour], smoothing: Smoothing) -> Vec<Contour> {
+    contours
+        .iter()
+        .map(|c| {
+            if c.points.len() < 3 {
+                return c.clone();
+            }
+            let points = match smoothing {
+                Smoothing::Chaikin { iterations } => {
+                    (0..iterations).fold(c.points.clone(), |points, _| chaikin(&points, c.closed))
+                }
+                Smoothing::CatmullRom { spacing } => catmull_rom(&c.points, c.closed, spacing),
+            };
+            Contour {
+                points,
+                closed: c.closed,
+            }
+        })
+        .collect()
+}
+
+fn chaikin(points: &[Vec2], closed: bool) -> Vec<Vec2> {
+    let n = points.len();
+    let mut result = Vec::with_capacity(n * 2);
+    if !closed {
+        result.push(points[0]);
+    }
+    let edges = if closed { n } else { n - 1 };
+    for i in 0..edges {
+        let p = points[i];
+        let q = points[(i + 1) % n];
+        // the ends of open contours keep their full first and last edge
+        if closed || i != 0 {
+            result.push(p.lerp(q, 0.25));
+        }
+        if closed || i != edges - 1 {
+            result.push(p.lerp(q, 0.75));
+        }
+    }
+    if !closed {
+        result.push(points[n - 1]);
+    }
+    result
+}
+
+fn catmull_rom(points: &[Vec2], closed: bool, spacing: f32) -> Vec<Vec2> {
+    let n = points.len();
+    let point = |i: isize| {
+        if closed {
+            points[i.rem_euclid(n as isize) as usize]
+        } else {
+            points[i.clamp(0, n as isize - 1) as usize]
+        }
+    };
+
+    let mut result = Vec::new();
+    let edges = if closed { n } else { n - 1 };
+    for i in 0..edges as isize {
+        let (p0, p1, p2, p3) = (point(i - 1), point(i), point(i + 1), point(i + 2));
+        let steps = (p1.distance(p2) / spacing.max(f32::EPSILON))
+            .ceil()
+            .max(1.0) as usize;
+        for s in 0..steps {
+            result.push(centripetal(p0, p1, p2, p3, s as f32 / steps as f32));
+        }
+    }
+    if !closed {
+        result.push(points[n - 1]);
+    }
+    result
+}
+
+/// Point at `t` between `p1` and `p2` on the centripetal spline
+fn centripetal(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
+    let knot = |a: Vec2, b: Vec2| a.distance(b).sqrt().max(1e-4);
+    let t0 = 0.0;
+    let t1 = t0 + knot(p0, p1);
+    let t2 = t1 + knot(p1, p2);
+    let t3 = t2 + knot(p2, p3);
+    let t = t1 + (t2 - t1) * t;
+
+    let a1 = p0 * ((t1 - t) / (t1 - t0)) + p1 * ((t - t0) / (t1 - t0));
+    let a2 = p1 * ((t2 - t) / (t2 - t1)) + p2 * ((t - t1) / (t2 - t1));
+    let a3 = p2 * ((t3 - t) / (t3 - t2)) + p3 * ((t - t2) / (t3 - t2));
+    let b1 = a1 * ((t2 - t) / (t2 - t0)) + a2 * ((t - t0) / (t2 - t0));
+    let b2 = a2 * ((t3 - t) / (t3 - t1)) + a3 * ((t - t1) / (t3 - t1));
+    b1 * ((t2 - t) / (t2 - t1)) + b2 * ((t - t1) / (t2 - t1))
+}
@@ -1,11 +1,38 @@
-use bevy::prelude::*;
-use crate::value_plain::ValuePlain;
+use crate::contour::{self, ContourFilter};
+use crate::dual_contouring::DualContouring;
+use crate::extrusion::Extrusion;
+use crate::labels::{Connectivity, Labels};
 use crate::marching_squares::MarchingSquares;
+use crate::marching_triangles::{Lattice, MarchingTriangles};
+use crate::mesh_attributes::MeshSettings;
+use crate::polygon;
+use crate::raycast::{self, RayHit};
+use crate::region::{self, Region};
+use crate::sdf::{self, DistanceMethod};
+use crate::value_plain::ValuePlain;
+use bevy::prelude::*;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Mesher {
+    #[default]
+    Squares,
+    Triangles(Lattice),
+    DualContouring,
+    /// Triangulates the closed contours after running the contour filters
+    Polygons,
+}
 
 #[derive(Debug, Default, Component)]
 pub struct ThresholdLayer {
     pub threshold: f32,
     pub normalized_values: Vec<bool>,
+    pub settings: MeshSettings,
+    pub extrusion: Option<Extrusion>,
+    pub mesher: Mesher,
+    pub contour_filters: Vec<ContourFilter>,
+    /// Grows the region by this distance before meshing, negative shrinks
+    pub offset: f32,
 }
 
 impl ThresholdLayer {
@@ -13,8 +40,100 @@ impl ThresholdLayer {
         Self {
             threshold,
             normalized_values: vec![false; (width * height) as usize],
+            settings: MeshSettings::default(),
+            extrusion: None,
+            mesher: Mesher::default(),
+            contour_filters: Vec::new(),
+            offset: 0.0,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: MeshSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_extrusion(mut self, extrusion: Extrusion) -> Self {
+        self.extrusion = Some(extrusion);
+        self
+    }
+
+    pub fn with_mesher(mut self, mesher: Mesher) -> Self {
+        self.mesher = mesher;
+        self
+    }
+
+    pub fn with_contour_filter(mut self, filter: ContourFilter) -> Self {
+        self.contour_filters.push(filter);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: f32) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn contours(&self, plain: &ValuePlain) -> Vec<contour::Contour> {
+        self.contour_filters.iter().fold(
+            contour::closed_contours(plain, self.threshold),
+            |contours, f| f.apply(&contours),
+        )
+    }
+
+    /// The field with `offset` applied, borrowed when there is none. A non
+    /// zero offset computes a distance field over the whole plain.
+    pub fn offset_plain<'a>(&self, plain: &'a ValuePlain) -> Cow<'a, ValuePlain> {
+        if self.offset != 0.0 {
+            Cow::Owned(self.grow(plain, self.offset))
+        } else {
+            Cow::Borrowed(plain)
         }
     }
+
+    /// Measurements of every connected inside region, after the offset and
+    /// the contour filters
+    pub fn regions(&self, plain: &ValuePlain) -> Vec<Region> {
+        let plain = self.offset_plain(plain);
+        region::regions(&self.contours(&plain), plain.bounds())
+    }
+
+    /// Whether the bilinear interpolated field, after the offset, is above
+    /// the threshold at a world point, no mesh needed. With an offset every
+    /// call grows the whole plain, use `offset_plain` once for many points.
+    pub fn contains(&self, plain: &ValuePlain, point: Vec2) -> bool {
+        self.offset_plain(plain)
+            .sample(point)
+            .is_some_and(|v| v > self.threshold)
+    }
+
+    /// First point along the ray where the field, after the offset, crosses
+    /// the threshold
+    pub fn raycast(
+        &self,
+        plain: &ValuePlain,
+        origin: Vec2,
+        direction: Vec2,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        let plain = self.offset_plain(plain);
+        raycast::raycast(&plain, self.threshold, origin, direction, max_distance)
+    }
+
+    /// Signed distance to the contour of this layer, positive inside
+    pub fn distance_field(&self, plain: &ValuePlain, method: DistanceMethod) -> ValuePlain {
+        sdf::signed_distance(plain, self.threshold, method)
+    }
+
+    /// Field of this layer's region grown by `distance`, meshes with the
+    /// same threshold
+    pub fn grow(&self, plain: &ValuePlain, distance: f32) -> ValuePlain {
+        sdf::grow(plain, self.threshold, distance, DistanceMethod::Sweeping)
+    }
+
+    pub fn shrink(&self, plain: &ValuePlain, distance: f32) -> ValuePlain {
+        sdf::shrink(plain, self.threshold, distance, DistanceMethod::Sweeping)
+    }
+
     pub fn update_values(&mut self, grid: &ValuePlain) {
         for (n, v) in self.normalized_values.iter_mut().zip(grid.values.iter()) {
             *n = v > &self.threshold;
@@ -27,13 +146,49 @@ impl ThresholdLayer {
         mesh_handle: Handle<Mesh>,
         meshes: &mut ResMut<Assets<Mesh>>,
     ) {
-        self.update_values(plain);
-        let mesh = MarchingSquares::from_plain(plain, self);
+        let plain = self.offset_plain(plain);
+        self.update_values(&plain);
+        let mesh = self.mesh(&plain);
         if let Some(m) = meshes.get_mut(&mesh_handle) {
             *m = mesh;
         }
     }
 
+    /// One mesh per connected region after the offset, in label order
+    pub fn region_meshes(&mut self, plain: &ValuePlain, connectivity: Connectivity) -> Vec<Mesh> {
+        let offset_plain = self.offset_plain(plain);
+        self.update_values(&offset_plain);
+        let labels = Labels::from_layer(&offset_plain, self, connectivity);
+        let meshes = (0..labels.count)
+            .map(|label| {
+                let masked = labels.mask(&offset_plain, self.threshold, label);
+                self.update_values(&masked);
+                self.mesh(&masked)
+            })
+            .collect();
+        self.update_values(&offset_plain);
+        meshes
+    }
+
+    /// Mesh of the current normalized values
+    fn mesh(&self, plain: &ValuePlain) -> Mesh {
+        match (&self.extrusion, self.mesher) {
+            (Some(extrusion), _) => extrusion.mesh_from_plain(plain, self),
+            (None, Mesher::Squares) => {
+                MarchingSquares::with_settings(self.settings.clone()).mesh_from_plain(plain, self)
+            }
+            (None, Mesher::Triangles(lattice)) => MarchingTriangles::new(lattice)
+                .with_settings(self.settings.clone())
+                .mesh_from_plain(plain, self),
+            (None, Mesher::DualContouring) => DualContouring::default()
+                .with_settings(self.settings.clone())
+                .mesh_from_plain(plain, self),
+            (None, Mesher::Polygons) => {
+                polygon::mesh_from_contours(&self.contours(plain), &self.settings, plain.bounds())
+            }
+        }
+    }
+
     pub fn calculate_iso(&self, plain: &ValuePlain, i: u32, j: u32) -> u8 {
         if i >= plain.width - 2 || j > plain.height - 2 {
             return 0;
@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 
-#[derive(Debug, Default, Component)]
+use crate::ball::Metaball;
+use crate::csg::Csg;
+
+#[derive(Debug, Clone, Default, Component)]
 pub struct ValuePlain {
     pub width: u32,
     pub height: u32,
@@ -30,9 +33,175 @@ impl ValuePlain {
         }
     }
 
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        self.positions.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), p| (min.min(p.truncate()), max.max(p.truncate())),
+        )
+    }
+
     pub fn update(&mut self, f: &impl Fn(f32, f32) -> f32) {
         for (i, pos) in self.positions.iter().enumerate() {
             self.values[i] = f(pos[0], pos[1]);
         }
     }
+
+    /// Adds a ball to the values. Kernels with a finite support only touch
+    /// the samples within it, so many small balls stay cheap.
+    pub fn splat(&mut self, ball: &Metaball) {
+        let (min, max) = match ball.kernel.support(ball.r) {
+            Some(support) => {
+                let g1 = self.grid_coords(ball.pos - Vec2::splat(support));
+                let g2 = self.grid_coords(ball.pos + Vec2::splat(support));
+                let last = Vec2::new(self.width as f32 - 1.0, self.height as f32 - 1.0);
+                (
+                    g1.min(g2).ceil().max(Vec2::ZERO),
+                    g1.max(g2).floor().min(last),
+                )
+            }
+            None => (
+                Vec2::ZERO,
+                Vec2::new(self.width as f32 - 1.0, self.height as f32 - 1.0),
+            ),
+        };
+        if min.x > max.x || min.y > max.y {
+            return;
+        }
+        for j in (min.y as u32)..=(max.y as u32) {
+            for i in (min.x as u32)..=(max.x as u32) {
+                let index = self.index(i, j);
+                let p = self.positions[index];
+                self.values[index] += ball.value(p.x, p.y);
+            }
+        }
+    }
+
+    pub fn index(&self, i: u32, j: u32) -> usize {
+        (i + j * self.width) as usize
+    }
+
+    /// World size of one cell, the y axis points down the rows so `y` is
+    /// negative
+    pub fn spacing(&self) -> Vec2 {
+        let origin = self.positions[0];
+        Vec2::new(
+            self.positions[1.min(self.positions.len() - 1)].x - origin.x,
+            self.positions[(self.width as usize).min(self.positions.len() - 1)].y - origin.y,
+        )
+    }
+
+    /// Fractional grid coordinates (i, j) of a world point
+    pub fn grid_coords(&self, point: Vec2) -> Vec2 {
+        (point - self.positions[0].truncate()) / self.spacing()
+    }
+
+    pub fn world_position(&self, grid: Vec2) -> Vec2 {
+        self.positions[0].truncate() + grid * self.spacing()
+    }
+
+    /// Bilinear interpolation of the values, `None` outside of the plain
+    pub fn sample(&self, point: Vec2) -> Option<f32> {
+        let g = self.grid_coords(point);
+        let max = Vec2::new(self.width as f32 - 1.0, self.height as f32 - 1.0);
+        if !(g.cmpge(Vec2::ZERO).all() && g.cmple(max).all()) {
+            return None;
+        }
+        let i = (g.x as u32).min(self.width.saturating_sub(2));
+        let j = (g.y as u32).min(self.height.saturating_sub(2));
+        let s = g.x - i as f32;
+        let r = g.y - j as f32;
+        let value = |i: u32, j: u32| self.values[self.index(i, j)];
+        let top = value(i, j) * (1.0 - s) + value(i + 1, j) * s;
+        let bottom = value(i, j + 1) * (1.0 - s) + value(i + 1, j + 1) * s;
+        Some(top * (1.0 - r) + bottom * r)
+    }
+
+    /// Central differences gradient in world space
+    pub fn gradient(&self, i: u32, j: u32) -> Vec2 {
+        let axis = |p1: usize, p2: usize, along: usize| {
+            let d = self.positions[p2][along] - self.positions[p1][along];
+            if d != 0.0 {
+                (self.values[p2] - self.values[p1]) / d
+            } else {
+                0.0
+            }
+        };
+        Vec2::new(
+            axis(
+                self.index(i.saturating_sub(1), j),
+                self.index((i + 1).min(self.width - 1), j),
+                0,
+            ),
+            axis(
+                self.index(i, j.saturating_sub(1)),
+                self.index(i, (j + 1).min(self.height - 1)),
+                1,
+            ),
+        )
+    }
+
+    /// Combines the values of a plain of the same size into this one
+    pub fn combine(&mut self, other: &ValuePlain, csg: Csg) {
+        assert_eq!(
+            (self.width, self.height),
+            (other.width, other.height),
+            "plains of different size"
+        );
+        for (a, b) in self.values.iter_mut().zip(other.values.iter()) {
+            *a = csg.apply(*a, *b);
+        }
+    }
+
+    /// Adds a constant, moves the threshold of a field to zero before
+    /// combining it
+    pub fn shift(&mut self, amount: f32) {
+        for v in self.values.iter_mut() {
+            *v += amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ball::Kernel;
+
+    #[test]
+    fn splat_matches_the_sum_over_every_sample() {
+        let kernels = [
+            Kernel::InverseSquare,
+            Kernel::Wyvill { support: 2.0 },
+            Kernel::Blinn { blobbiness: 0.5 },
+            Kernel::Nishimura { support: 1.5 },
+        ];
+        // inside, on the border and entirely off the plain
+        let centers = [
+            Vec2::new(0.3, -2.2),
+            Vec2::new(-15.5, 11.0),
+            Vec2::new(6.1, 4.7),
+            Vec2::new(40.0, 3.0),
+        ];
+        let balls: Vec<Metaball> = kernels
+            .iter()
+            .zip(centers.iter())
+            .flat_map(|(kernel, pos)| {
+                [2.5, -1.0].map(|weight| Metaball {
+                    pos: *pos,
+                    r: 3.0,
+                    kernel: *kernel,
+                    weight,
+                })
+            })
+            .collect();
+
+        let mut splatted = ValuePlain::new(32, 24);
+        for ball in &balls {
+            splatted.splat(ball);
+        }
+        let mut brute_force = ValuePlain::new(32, 24);
+        brute_force.update(&|x, y| balls.iter().map(|b| b.value(x, y)).sum());
+        for (s, b) in splatted.values.iter().zip(brute_force.values.iter()) {
+            assert!((s - b).abs() < 1e-4, "{} != {}", s, b);
+        }
+    }
 }
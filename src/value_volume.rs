@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+/// 3D counterpart of `ValuePlain`. Points are laid out row by row like in
+/// the plain, then slice by slice along Z.
+#[derive(Debug, Default, Component)]
+pub struct ValueVolume {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub positions: Vec<Vec3>,
+    pub values: Vec<f32>,
+}
+
+impl ValueVolume {
+    pub fn new(width: u32, height: u32, depth: u32) -> Self {
+        let total_points = width * height * depth;
+        let mut positions = Vec::with_capacity(total_points as usize);
+        let half_width = width as f32 * 0.5;
+        let half_height = height as f32 * 0.5;
+        let half_depth = depth as f32 * 0.5;
+        for z in 0..depth {
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    let position = Vec3::new(
+                        x as f32 - half_width,
+                        y as f32 - half_height,
+                        z as f32 - half_depth,
+                    ) + Vec3::splat(0.5);
+                    positions.push(position);
+                }
+            }
+        }
+        let values = vec![0.0; total_points as usize];
+
+        Self {
+            width,
+            height,
+            depth,
+            positions,
+            values,
+        }
+    }
+
+    pub fn index(&self, i: u32, j: u32, k: u32) -> usize {
+        (i + j * self.width + k * self.width * self.height) as usize
+    }
+
+    pub fn bounds(&self) -> (Vec3, Vec3) {
+        self.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(*p), max.max(*p)),
+        )
+    }
+
+    pub fn update(&mut self, f: &impl Fn(f32, f32, f32) -> f32) {
+        for (i, pos) in self.positions.iter().enumerate() {
+            self.values[i] = f(pos[0], pos[1], pos[2]);
+        }
+    }
+
+    /// Central differences gradient in world space
+    pub fn gradient(&self, i: u32, j: u32, k: u32) -> Vec3 {
+        let axis = |p1: usize, p2: usize, along: usize| {
+            let d = self.positions[p2][along] - self.positions[p1][along];
+            if d != 0.0 {
+                (self.values[p2] - self.values[p1]) / d
+            } else {
+                0.0
+            }
+        };
+        Vec3::new(
+            axis(
+                self.index(i.saturating_sub(1), j, k),
+                self.index((i + 1).min(self.width - 1), j, k),
+                0,
+            ),
+            axis(
+                self.index(i, j.saturating_sub(1), k),
+                self.index(i, (j + 1).min(self.height - 1), k),
+                1,
+            ),
+            axis(
+                self.index(i, j, k.saturating_sub(1)),
+                self.index(i, j, (k + 1).min(self.depth - 1)),
+                2,
+            ),
+        )
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::ecs::system::{lifetimeless::SRes, SystemParamItem};
+use bevy::pbr::{MaterialPipeline, MaterialPlugin};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::{
+    mesh::MeshVertexBufferLayout,
+    render_asset::{PrepareAssetError, RenderAsset},
+    render_resource::{
+        BindGroup, BindGroupDescriptor, BindGroupLayout, BindGroupLayoutDescriptor,
+        RenderPipelineDescriptor, SpecializedMeshPipelineError,
+    },
+    renderer::RenderDevice,
+};
+
+const VERTEX_COLOR_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5d0b_8c3e_91a4_27f6);
+
+/// Registers `VertexColorMaterial` and its shader
+pub struct VertexColorPlugin;
+
+impl Plugin for VertexColorPlugin {
+    fn build(&self, app: &mut App) {
+        app.world.resource_mut::<Assets<Shader>>().set_untracked(
+            VERTEX_COLOR_SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("vertex_color.wgsl")),
+        );
+        app.add_plugin(MaterialPlugin::<VertexColorMaterial>::default());
+    }
+}
+
+/// Draws meshes with their `ATTRIBUTE_COLOR`, lit by the first directional
+/// light and the ambient light. `StandardMaterial` ignores vertex colors, so
+/// meshes built with a `ColorRamp` need this one.
+/// Meshes without colors are not drawn.
+#[derive(Debug, Clone, Default, TypeUuid)]
+#[uuid = "1e6c2f4a-8d3b-4b7e-9a51-3f0c6d2e8b14"]
+pub struct VertexColorMaterial;
+
+pub struct GpuVertexColorMaterial {
+    bind_group: BindGroup,
+}
+
+impl RenderAsset for VertexColorMaterial {
+    type ExtractedAsset = VertexColorMaterial;
+    type PreparedAsset = GpuVertexColorMaterial;
+    type Param = (SRes<RenderDevice>, SRes<MaterialPipeline<Self>>);
+
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        self.clone()
+    }
+
+    fn prepare_asset(
+        _material: Self::ExtractedAsset,
+        (render_device, pipeline): &mut SystemParamItem<Self::Param>,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("vertex_color_material_bind_group"),
+            layout: &pipeline.material_layout,
+            entries: &[],
+        });
+        Ok(GpuVertexColorMaterial { bind_group })
+    }
+}
+
+impl Material for VertexColorMaterial {
+    fn bind_group(material: &GpuVertexColorMaterial) -> &BindGroup {
+        &material.bind_group
+    }
+
+    fn bind_group_layout(render_device: &RenderDevice) -> BindGroupLayout {
+        render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("vertex_color_material_layout"),
+            entries: &[],
+        })
+    }
+
+    fn vertex_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(VERTEX_COLOR_SHADER_HANDLE.typed())
+    }
+
+    fn fragment_shader(_asset_server: &AssetServer) -> Option<Handle<Shader>> {
+        Some(VERTEX_COLOR_SHADER_HANDLE.typed())
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // the mesh pipeline asks for uvs and tangents, the shader only needs
+        // positions, normals and colors
+        descriptor.vertex.buffers = vec![layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(2),
+        ])?];
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+#import bevy_pbr::mesh_view_bind_group
+#import bevy_pbr::mesh_struct
+
+[[group(2), binding(0)]]
+var<uniform> mesh: Mesh;
+
+struct Vertex {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] color: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_normal: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
+    out.clip_position = view.view_proj * world_position;
+    out.world_normal = mat3x3<f32>(
+        mesh.inverse_transpose_model[0].xyz,
+        mesh.inverse_transpose_model[1].xyz,
+        mesh.inverse_transpose_model[2].xyz
+    ) * vertex.normal;
+    // colors are packed linear rgba, red in the lowest byte
+    out.color = unpack4x8unorm(vertex.color);
+    return out;
+}
+
+struct FragmentInput {
+    [[builtin(front_facing)]] is_front: bool;
+    [[location(0)]] world_normal: vec3<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(fragment)]]
+fn fragment(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    var normal = normalize(in.world_normal);
+    if (!in.is_front) {
+        normal = -normal;
+    }
+    var light = lights.ambient_color.rgb;
+    if (lights.n_directional_lights > 0u) {
+        let sun = lights.directional_lights[0];
+        // the color is already premultiplied by illuminance and exposure
+        light = light + sun.color.rgb * max(dot(normal, sun.direction_to_light), 0.0);
+    }
+    return vec4<f32>(in.color.rgb * light, in.color.a);
+}